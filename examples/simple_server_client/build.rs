@@ -1,28 +1,25 @@
 extern crate tokio_thrift_codegen;
 
-use tokio_thrift_codegen::{compile, find_rust_namespace};
-use tokio_thrift_codegen::parser::Document;
+use tokio_thrift_codegen::{compile_in, find_rust_namespace, load};
 use std::env;
 use std::fs::File;
 use std::path::Path;
-use std::io::Read;
 
 
 fn main() {
     let dst = env::var_os("OUT_DIR").unwrap();
-    let mut input = File::open("src/hello.thrift").expect("input file does not exist.");
-    let mut s = String::new();
-    input.read_to_string(&mut s).expect("file io error");
-    let doc = Document::parse(&s)
-        .expect("failed to parse thrift file")
-        .expect("EOF while parsing thrift file");
+    // the second argument lists directories to search for included files
+    // and errors display with the file, line and column they come from
+    let docs = load(Path::new("src/hello.thrift"), &[]).unwrap_or_else(|e| panic!("{}", e));
 
-    let module = {
-        let ns = find_rust_namespace(&doc).expect("cannot find namespace");
-        Path::new(&dst).join(&ns.module).with_extension("rs")
-    };
-    let mut output = File::create(module).expect("error creating the module.");
-
-    compile(doc, &mut output).expect("failed to generate code");
+    // each file, included ones too, is generated into the module named by its namespace
+    for doc in docs.iter() {
+        let module = {
+            let ns = find_rust_namespace(doc).expect("cannot find namespace");
+            Path::new(&dst).join(&ns.module).with_extension("rs")
+        };
+        let mut output = File::create(module).expect("error creating the module.");
 
+        compile_in(doc.clone(), &docs, &mut output).unwrap_or_else(|e| panic!("{}", e));
+    }
 }
@@ -30,7 +30,8 @@ pub fn main() {
 
     let hello_ret = core.run(hello_ret).expect("rpc failed");
     let hello_name_ret1 = core.run(hello_name_ret1).expect("rpc failed");
-    let hello_name_ret2 = core.run(hello_name_ret2).expect("rpc failed");
+    // the server fails this one, which comes back as an `ApplicationException`
+    let hello_name_ret2 = core.run(hello_name_ret2);
 
     println!("RESPONSE: {:?}", hello_ret);
     println!("RESPONSE: {:?}", hello_name_ret1);
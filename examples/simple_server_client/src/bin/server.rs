@@ -7,6 +7,7 @@ extern crate tokio_thrift;
 
 use futures::{finished, failed, Future};
 use futures::future::BoxFuture;
+use tokio_thrift::{ApplicationException, ApplicationExceptionKind};
 use tokio_thrift::tokio::new_tcp_server;
 use simple_server_client::thrift::*;
 
@@ -15,19 +16,19 @@ struct HelloServerImpl;
 
 // implement HelloService
 impl HelloService for HelloServerImpl {
-    fn hello_name(&self, name: String) -> BoxFuture<String, ()> {
+    fn hello_name(&self, name: String) -> BoxFuture<String, Hellohello_nameError> {
         println!("GOT: {:?}", name);
         if name == "error".as_ref() {
-            failed::<String, _>(()).boxed()
+            failed(ApplicationException::new(ApplicationExceptionKind::InternalError, "asked to fail").into()).boxed()
         } else {
-            finished::<_, ()>(format!("Hello, {}", name)).boxed()
+            finished::<_, Hellohello_nameError>(format!("Hello, {}", name)).boxed()
         }
 
     }
 
-    fn hello(&self) -> BoxFuture<String, ()> {
+    fn hello(&self) -> BoxFuture<String, HellohelloError> {
         println!("CALLED");
-        finished::<_, ()>(format!("Hello, World")).boxed()
+        finished::<_, HellohelloError>(format!("Hello, World")).boxed()
     }
 }
 
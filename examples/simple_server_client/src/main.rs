@@ -18,12 +18,12 @@ struct HelloServerImpl;
 
 // implement HelloService
 impl HelloService for HelloServerImpl {
-    fn hello_name(&self, name: String) -> BoxFuture<String, ()> {
+    fn hello_name(&self, name: String) -> BoxFuture<String, Hellohello_nameError> {
         println!("GOT: {:?}", name);
         Box::new(ok(format!("Hello, {}", name)))
     }
 
-    fn hello(&self) -> BoxFuture<String, ()> {
+    fn hello(&self) -> BoxFuture<String, HellohelloError> {
         println!("CALLED");
         Box::new(ok(format!("Hello, World")))
     }
@@ -6,28 +6,36 @@ extern crate docopt;
 
 use docopt::Docopt;
 
-use std::io::Read;
 use std::fs::File;
-use std::path::Path;
-use tokio_thrift_codegen::{compile, find_rust_namespace};
-use tokio_thrift_codegen::parser::Document;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use tokio_thrift_codegen::{compile_in, find_rust_namespace, load, Error};
 
 const USAGE: &'static str = "
 Thrust: Thrift compiler for Rust
 
 Usage:
-  tokio_thrift <input> <output>
+  tokio_thrift [-I <dir>]... <input> <output>
   tokio_thrift --version
 
 Options:
   -h --help     Show this screen.
   --version     Show version.
+  -I <dir>      Look for included files in <dir> too.
 ";
 
+#[allow(non_snake_case)]
 #[derive(Debug, RustcDecodable)]
 struct Args {
     arg_input: String,
-    arg_output: String
+    arg_output: String,
+    flag_I: Vec<String>,
+}
+
+fn fail(e: Error) -> ! {
+    writeln!(io::stderr(), "error: {}", e).expect("failed to write to stderr");
+    process::exit(1)
 }
 
 fn main() {
@@ -37,19 +45,18 @@ fn main() {
 
     debug!("{:?}", args);
 
-    let mut input = File::open(args.arg_input).expect("input file does not exist.");
-    let mut s = String::new();
-    input.read_to_string(&mut s).expect("file io error");
-    let doc = Document::parse(&s)
-        .expect("failed to parse thrift file")
-        .expect("EOF while parsing thrift file");
-    println!("{:?}", doc);
-
-    let module = {
-        let ns = find_rust_namespace(&doc).expect("cannot find namespace");
-        Path::new(&args.arg_output).join(&ns.module).with_extension("rs")
-    };
-    let mut output = File::create(module).expect("error creating the module.");
-
-    compile(doc, &mut output).expect("failed to generate code");
+    let include_dirs: Vec<PathBuf> = args.flag_I.iter().map(PathBuf::from).collect();
+    let docs = load(Path::new(&args.arg_input), &include_dirs).unwrap_or_else(|e| fail(e));
+
+    // every file, included ones too, gets its own module
+    for doc in docs.iter() {
+        debug!("{:?}", doc);
+        let module = {
+            let ns = find_rust_namespace(doc).expect("cannot find namespace");
+            Path::new(&args.arg_output).join(&ns.module).with_extension("rs")
+        };
+        let mut output = File::create(module).expect("error creating the module.");
+
+        compile_in(doc.clone(), &docs, &mut output).unwrap_or_else(|e| fail(e));
+    }
 }
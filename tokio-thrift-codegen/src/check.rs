@@ -0,0 +1,247 @@
+//! Checking that a document makes sense before any code is generated from it.
+use parser::*;
+use scope::Scope;
+use super::Error;
+
+/// Reports undefined and duplicate names, recursive typedefs, and map keys or set
+/// elements that cannot be hashed, all of them at once.
+pub fn check(doc: &Document, scope: &Scope) -> Result<(), Error> {
+    let mut checker = Checker {
+        scope: scope,
+        errors: Vec::new(),
+    };
+    checker.document(doc);
+    if checker.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Semantic(checker.errors.join("\n")))
+    }
+}
+
+struct Checker<'a> {
+    scope: &'a Scope,
+    errors: Vec<String>,
+}
+
+impl<'a> Checker<'a> {
+    fn document(&mut self, doc: &Document) {
+        let mut defined: Vec<&str> = Vec::new();
+        for def in doc.definitions.iter() {
+            if defined.contains(&def.ident()) {
+                self.errors.push(format!("{} is defined twice", def.ident()));
+            }
+            defined.push(def.ident());
+
+            match *def {
+                Definition::Const(ref c) => {
+                    let owner = format!("const {}", c.ident);
+                    self.ty(&owner, &c.ty);
+                    self.value(&owner, &c.value);
+                }
+                Definition::Typedef(ref t) => {
+                    self.ty(&format!("typedef {}", t.ident), &t.ty);
+                    if self.refers_to(&t.ty, &t.ident, &mut Vec::new()) {
+                        self.errors.push(format!("typedef {} refers to itself", t.ident));
+                    }
+                }
+                Definition::Enum(ref e) => self.enum_(e),
+                Definition::Struct(ref s) => self.fields(&format!("struct {}", s.ident), &s.fields),
+                Definition::Union(ref u) => self.fields(&format!("union {}", u.ident), &u.fields),
+                Definition::Exception(ref e) => self.fields(&format!("exception {}", e.ident), &e.fields),
+                Definition::Service(ref s) => self.service(s),
+            }
+        }
+    }
+
+    fn enum_(&mut self, e: &Enum) {
+        let mut next = 0;
+        let mut values: Vec<(&str, i64)> = Vec::new();
+        for variant in e.variants.iter() {
+            let value = variant.seq.unwrap_or(next);
+            next = value + 1;
+            if values.iter().any(|&(ident, _)| ident == variant.ident) {
+                self.errors.push(format!("enum {}: variant {} is declared twice", e.ident, variant.ident));
+            }
+            if let Some(&(other, _)) = values.iter().find(|&&(_, v)| v == value) {
+                self.errors.push(format!("enum {}: variants {} and {} both have value {}",
+                                         e.ident, other, variant.ident, value));
+            }
+            values.push((&variant.ident, value));
+        }
+    }
+
+    fn service(&mut self, s: &Service) {
+        if let Some(ref extends) = s.extends {
+            match self.scope.find(extends) {
+                Some(&Definition::Service(_)) => (),
+                Some(_) => self.errors.push(format!("service {}: {} is not a service", s.ident, extends)),
+                None => self.errors.push(format!("service {}: undefined service {}", s.ident, extends)),
+            }
+        }
+        let mut defined: Vec<&str> = Vec::new();
+        for method in s.methods.iter() {
+            if defined.contains(&&method.ident[..]) {
+                self.errors.push(format!("service {}: method {} is declared twice", s.ident, method.ident));
+            }
+            defined.push(&method.ident);
+
+            let owner = format!("{}.{}", s.ident, method.ident);
+            self.ty(&owner, &method.ty);
+            self.fields(&format!("arguments of {}", owner), &method.args);
+            if let Some(ref throws) = method.throws {
+                let owner = format!("exceptions of {}", owner);
+                self.fields(&owner, throws);
+                for field in throws.iter() {
+                    let is_exception = match field.ty {
+                        Ty::Ident(ref name) => match self.scope.find(name) {
+                            Some(&Definition::Exception(_)) => true,
+                            // undefined names are reported already
+                            None => true,
+                            _ => false,
+                        },
+                        _ => false,
+                    };
+                    if !is_exception {
+                        self.errors.push(format!("{}: field {} is not an exception", owner, field.ident));
+                    }
+                }
+            }
+        }
+    }
+
+    fn fields(&mut self, owner: &str, fields: &[StructField]) {
+        for field in fields.iter() {
+            let owner = format!("{}: field {}", owner, field.ident);
+            self.ty(&owner, &field.ty);
+            if let Some(ref value) = field.value {
+                self.value(&owner, value);
+            }
+        }
+    }
+
+    fn ty(&mut self, owner: &str, ty: &Ty) {
+        match *ty {
+            Ty::List(ref ty) | Ty::Custom(_, ref ty) => self.ty(owner, ty),
+            Ty::Set(ref ty) => {
+                self.ty(owner, ty);
+                if !self.hashable(ty, 0) {
+                    self.errors.push(format!("{}: set elements of type {} cannot be hashed", owner, ty.to_string()));
+                }
+            }
+            Ty::Map(ref key, ref value) => {
+                self.ty(owner, key);
+                self.ty(owner, value);
+                if !self.hashable(key, 0) {
+                    self.errors.push(format!("{}: map keys of type {} cannot be hashed", owner, key.to_string()));
+                }
+            }
+            Ty::Ident(ref name) => {
+                match self.scope.find(name) {
+                    Some(&Definition::Typedef(_)) |
+                    Some(&Definition::Enum(_)) |
+                    Some(&Definition::Struct(_)) |
+                    Some(&Definition::Union(_)) |
+                    Some(&Definition::Exception(_)) => (),
+                    Some(_) => self.errors.push(format!("{}: {} is not a type", owner, name)),
+                    None => self.errors.push(format!("{}: undefined type {}", owner, name)),
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Whether the generated type for `ty` implements `Hash`. Only enums do among the
+    /// generated ones, and `rust.type` ones are up to the user.
+    fn hashable(&self, ty: &Ty, depth: usize) -> bool {
+        if depth > 64 {
+            // a recursive typedef, reported on its own
+            return true;
+        }
+        match *ty {
+            Ty::Double | Ty::Set(_) | Ty::Map(_, _) => false,
+            Ty::List(ref ty) => self.hashable(ty, depth + 1),
+            Ty::Ident(ref name) => match self.scope.find(name) {
+                Some(&Definition::Typedef(ref t)) => self.hashable(&t.ty, depth + 1),
+                Some(&Definition::Struct(_)) |
+                Some(&Definition::Union(_)) |
+                Some(&Definition::Exception(_)) => false,
+                _ => true,
+            },
+            _ => true,
+        }
+    }
+
+    /// Whether `ty` stands for a type using the typedef `ident`. `seen` holds the
+    /// typedefs followed so far, to stop at cycles not going through `ident`.
+    fn refers_to(&self, ty: &Ty, ident: &str, seen: &mut Vec<String>) -> bool {
+        match *ty {
+            Ty::List(ref ty) | Ty::Set(ref ty) | Ty::Custom(_, ref ty) => self.refers_to(ty, ident, seen),
+            Ty::Map(ref key, ref value) => self.refers_to(key, ident, seen) || self.refers_to(value, ident, seen),
+            Ty::Ident(ref name) if name == ident => true,
+            Ty::Ident(ref name) if !seen.contains(name) => {
+                seen.push(name.clone());
+                match self.scope.find(name) {
+                    Some(&Definition::Typedef(ref t)) => self.refers_to(&t.ty, ident, seen),
+                    _ => false,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    fn value(&mut self, owner: &str, value: &ConstValue) {
+        match *value {
+            ConstValue::List(ref vs) => {
+                for v in vs.iter() {
+                    self.value(owner, v);
+                }
+            }
+            ConstValue::Map(ref kvs) => {
+                for &(ref k, ref v) in kvs.iter() {
+                    self.value(owner, k);
+                    self.value(owner, v);
+                }
+            }
+            ConstValue::Ident(ref name) if name != "true" && name != "false" => {
+                // `Color.RED` names a variant, `super::module::Color.RED` when included
+                let defined = match name.rfind('.') {
+                    Some(i) => match self.scope.find(&name[..i]) {
+                        Some(&Definition::Enum(ref e)) => e.variants.iter().any(|v| v.ident == name[i + 1..]),
+                        _ => false,
+                    },
+                    None => match self.scope.find(name) {
+                        Some(&Definition::Const(_)) => true,
+                        _ => false,
+                    },
+                };
+                if !defined {
+                    self.errors.push(format!("{}: undefined constant {}", owner, name));
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+#[test]
+fn test_check() {
+    let text = "typedef list<Id> Ids\ntypedef i32 Id\nstruct Foo {\n  1: map<Id, string> names;\n}\nservice Bar {\n  Foo get(1: Ids ids);\n}\n";
+    let doc = Document::parse(text).unwrap();
+    check(&doc, &Scope::new(&doc, &[])).unwrap();
+
+    let errors = |text: &str| {
+        let doc = Document::parse(text).unwrap();
+        match check(&doc, &Scope::new(&doc, &[])) {
+            Err(Error::Semantic(e)) => e,
+            ret => panic!("unexpected result {:?} for {}", ret, text),
+        }
+    };
+    assert_eq!(errors("struct Foo {\n  1: Bar bar;\n}\n"), "struct Foo: field bar: undefined type Bar");
+    assert_eq!(errors("struct Foo {\n}\nenum Foo {\n}\n"), "Foo is defined twice");
+    assert_eq!(errors("typedef list<B> A\ntypedef map<string, A> B\n"),
+               "typedef A refers to itself\ntypedef B refers to itself");
+    assert_eq!(errors("struct Foo {\n}\nstruct Bar {\n  1: set<Foo> foos;\n  2: map<double, i32> m;\n}\n"),
+               "struct Bar: field foos: set elements of type Foo cannot be hashed\n\
+                struct Bar: field m: map keys of type f64 cannot be hashed");
+    assert_eq!(errors("enum Color {\n  RED;\n}\nconst Color C = Color.BLUE;\n"), "const C: undefined constant Color.BLUE");
+}
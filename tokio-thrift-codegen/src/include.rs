@@ -0,0 +1,396 @@
+//! Loading thrift files together with the files they `include`.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use parser::*;
+use super::{Error, find_rust_namespace};
+
+/// Parses the thrift file at `path` and every file it includes.
+///
+/// Includes are looked up next to the including file first, then in `include_dirs`,
+/// like with the `-I` option of Apache's compiler. Every file becomes a Rust module
+/// named by its `rust` namespace, or by its file name if it has none, and qualified
+/// names like `shared.SharedStruct` are rewritten to `super::shared::SharedStruct`, as
+/// the modules are generated next to each other. Two files can't share a module.
+///
+/// Files come after the files they include, so the one at `path` is the last. The
+/// `ancestors` of every service are filled in, qualified likewise.
+pub fn load(path: &Path, include_dirs: &[PathBuf]) -> Result<Vec<Document>, Error> {
+    let mut loader = Loader {
+        include_dirs: include_dirs,
+        loading: Vec::new(),
+        modules: HashMap::new(),
+        documents: Vec::new(),
+    };
+    loader.load(path)?;
+    let mut docs = loader.documents;
+    resolve_extends(&mut docs)?;
+    Ok(docs)
+}
+
+struct Loader<'a> {
+    include_dirs: &'a [PathBuf],
+    // files whose includes are being loaded, to catch cycles
+    loading: Vec<PathBuf>,
+    modules: HashMap<PathBuf, String>,
+    documents: Vec<Document>,
+}
+
+impl<'a> Loader<'a> {
+    /// Loads the file at `path` unless it already was, and returns its module.
+    fn load(&mut self, path: &Path) -> Result<String, Error> {
+        let path = path.canonicalize()?;
+        if let Some(module) = self.modules.get(&path) {
+            return Ok(module.clone());
+        }
+        if self.loading.contains(&path) {
+            return Err(Error::Include(format!("{} includes itself", path.display())));
+        }
+
+        let mut text = String::new();
+        File::open(&path)?.read_to_string(&mut text)?;
+        let mut doc = match Document::parse(&text) {
+            Ok(doc) => doc,
+            Err(e) => return Err(Error::Parse(format!("{}:{}", path.display(), e))),
+        };
+
+        self.loading.push(path.clone());
+        let mut includes = HashMap::new();
+        for header in doc.headers.iter() {
+            if let Header::Include(ref include) = *header {
+                let found = self.find(&path, &include.path)?;
+                let module = self.load(&found)?;
+                let name = file_stem(Path::new(&include.path));
+                if let Some(other) = includes.insert(name.clone(), module.clone()) {
+                    if other != module {
+                        return Err(Error::Include(format!("{} includes two files named {}", path.display(), name)));
+                    }
+                }
+            }
+        }
+        self.loading.pop();
+        qualify_document(&mut doc, &includes);
+
+        let namespace = find_rust_namespace(&doc).map(|ns| ns.module.clone());
+        let module = match namespace {
+            Some(module) => module,
+            None => {
+                let module = file_stem(&path);
+                doc.headers.push(Header::Namespace(Namespace {
+                    lang: "rust".to_string(),
+                    module: module.clone(),
+                }));
+                module
+            }
+        };
+        if let Some((other, _)) = self.modules.iter().find(|&(_, m)| *m == module) {
+            return Err(Error::Include(format!("{} and {} are both module {}", other.display(), path.display(), module)));
+        }
+        self.modules.insert(path, module.clone());
+        self.documents.push(doc);
+        Ok(module)
+    }
+
+    fn find(&self, from: &Path, include: &str) -> Result<PathBuf, Error> {
+        let here = from.parent().map(|dir| dir.join(include));
+        here.into_iter()
+            .chain(self.include_dirs.iter().map(|dir| dir.join(include)))
+            .find(|candidate| candidate.is_file())
+            .ok_or_else(|| Error::Include(format!("cannot find {} included from {}", include, from.display())))
+    }
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_else(String::new)
+}
+
+/// Turns `include.Name` into the path of `Name` in the module of the included file.
+fn qualify(name: &mut String, includes: &HashMap<String, String>) {
+    let qualified = match name.find('.') {
+        Some(i) => includes.get(&name[..i]).map(|module| format!("super::{}::{}", module, &name[i + 1..])),
+        None => None,
+    };
+    if let Some(qualified) = qualified {
+        *name = qualified;
+    }
+}
+
+fn qualify_ty(ty: &mut Ty, includes: &HashMap<String, String>) {
+    match *ty {
+        Ty::List(ref mut ty) | Ty::Set(ref mut ty) => qualify_ty(ty, includes),
+        Ty::Map(ref mut key, ref mut value) => {
+            qualify_ty(key, includes);
+            qualify_ty(value, includes);
+        }
+        Ty::Ident(ref mut name) => qualify(name, includes),
+        _ => (),
+    }
+}
+
+fn qualify_value(value: &mut ConstValue, includes: &HashMap<String, String>) {
+    match *value {
+        ConstValue::List(ref mut vs) => {
+            for v in vs.iter_mut() {
+                qualify_value(v, includes);
+            }
+        }
+        ConstValue::Map(ref mut kvs) => {
+            for &mut (ref mut k, ref mut v) in kvs.iter_mut() {
+                qualify_value(k, includes);
+                qualify_value(v, includes);
+            }
+        }
+        ConstValue::Ident(ref mut name) => qualify(name, includes),
+        _ => (),
+    }
+}
+
+fn qualify_fields(fields: &mut Vec<StructField>, includes: &HashMap<String, String>) {
+    for field in fields.iter_mut() {
+        qualify_ty(&mut field.ty, includes);
+        if let Some(ref mut value) = field.value {
+            qualify_value(value, includes);
+        }
+    }
+}
+
+fn qualify_document(doc: &mut Document, includes: &HashMap<String, String>) {
+    for def in doc.definitions.iter_mut() {
+        match *def {
+            Definition::Const(ref mut c) => {
+                qualify_ty(&mut c.ty, includes);
+                qualify_value(&mut c.value, includes);
+            }
+            Definition::Typedef(ref mut t) => qualify_ty(&mut t.ty, includes),
+            Definition::Enum(_) => (),
+            Definition::Struct(ref mut s) => qualify_fields(&mut s.fields, includes),
+            Definition::Union(ref mut u) => qualify_fields(&mut u.fields, includes),
+            Definition::Exception(ref mut e) => qualify_fields(&mut e.fields, includes),
+            Definition::Service(ref mut s) => {
+                if let Some(ref mut extends) = s.extends {
+                    qualify(extends, includes);
+                }
+                for method in s.methods.iter_mut() {
+                    qualify_ty(&mut method.ty, includes);
+                    qualify_fields(&mut method.args, includes);
+                    if let Some(ref mut throws) = method.throws {
+                        qualify_fields(throws, includes);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Fills in the `ancestors` of the services in `docs`, which name each other qualified.
+pub fn resolve_extends(docs: &mut Vec<Document>) -> Result<(), Error> {
+    let modules: Vec<String> = docs.iter()
+        .map(|doc| find_rust_namespace(doc).map(|ns| ns.module.clone()).unwrap_or_else(String::new))
+        .collect();
+    for i in 0..docs.len() {
+        let mut resolved = Vec::new();
+        for def in docs[i].definitions.iter() {
+            if let Definition::Service(ref service) = *def {
+                resolved.push(ancestors(docs, &modules, i, service, &mut Vec::new())?);
+            }
+        }
+        let mut resolved = resolved.into_iter();
+        for def in docs[i].definitions.iter_mut() {
+            if let Definition::Service(ref mut service) = *def {
+                service.ancestors = resolved.next().expect("internal error");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The services `service` of `docs[doc]` extends, named as seen from that document.
+fn ancestors(docs: &[Document],
+             modules: &[String],
+             doc: usize,
+             service: &Service,
+             seen: &mut Vec<(usize, String)>)
+             -> Result<Vec<Service>, Error> {
+    let extends = match service.extends {
+        Some(ref extends) => extends,
+        None => return Ok(Vec::new()),
+    };
+    seen.push((doc, service.ident.clone()));
+    // qualified names are `super::module::Service`
+    let (parent_doc, name) = if extends.starts_with("super::") {
+        let mut parts = extends["super::".len()..].splitn(2, "::");
+        let module = parts.next().unwrap_or("");
+        let name = parts.next().unwrap_or("");
+        match modules.iter().position(|m| m == module) {
+            Some(i) => (i, name),
+            None => return Err(Error::Extends(format!("unknown service {}", extends))),
+        }
+    } else {
+        (doc, &extends[..])
+    };
+    if seen.contains(&(parent_doc, name.to_string())) {
+        return Err(Error::Extends(format!("service {} extends itself", service.ident)));
+    }
+    let parent = docs[parent_doc].definitions.iter()
+        .filter_map(|def| match *def {
+            Definition::Service(ref s) if s.ident == name => Some(s),
+            _ => None,
+        })
+        .next();
+    let parent = match parent {
+        Some(parent) => parent,
+        None => return Err(Error::Extends(format!("unknown service {}", extends))),
+    };
+
+    let mut chain = vec![Service { ancestors: Vec::new(), ..parent.clone() }];
+    chain.extend(ancestors(docs, modules, parent_doc, parent, seen)?);
+    if parent_doc != doc {
+        for service in chain.iter_mut() {
+            absolutize_service(service, &modules[parent_doc]);
+        }
+    }
+    Ok(chain)
+}
+
+/// Prefixes the names of `module` used in `service` with the module.
+fn absolutize_service(service: &mut Service, module: &str) {
+    absolutize(&mut service.ident, module);
+    if let Some(ref mut extends) = service.extends {
+        absolutize(extends, module);
+    }
+    for method in service.methods.iter_mut() {
+        absolutize_ty(&mut method.ty, module);
+        for arg in method.args.iter_mut() {
+            absolutize_ty(&mut arg.ty, module);
+        }
+        if let Some(ref mut throws) = method.throws {
+            for field in throws.iter_mut() {
+                absolutize_ty(&mut field.ty, module);
+            }
+        }
+    }
+}
+
+/// Prefixes the names of `module` used in the types and values of `def` with the module.
+pub fn absolutize_definition(def: &mut Definition, module: &str) {
+    match *def {
+        Definition::Const(ref mut c) => {
+            absolutize_ty(&mut c.ty, module);
+            absolutize_value(&mut c.value, module);
+        }
+        Definition::Typedef(ref mut t) => absolutize_ty(&mut t.ty, module),
+        Definition::Enum(_) => (),
+        Definition::Struct(ref mut s) => absolutize_fields(&mut s.fields, module),
+        Definition::Union(ref mut u) => absolutize_fields(&mut u.fields, module),
+        Definition::Exception(ref mut e) => absolutize_fields(&mut e.fields, module),
+        Definition::Service(_) => (),
+    }
+}
+
+fn absolutize_fields(fields: &mut Vec<StructField>, module: &str) {
+    for field in fields.iter_mut() {
+        absolutize_ty(&mut field.ty, module);
+        if let Some(ref mut value) = field.value {
+            absolutize_value(value, module);
+        }
+    }
+}
+
+fn absolutize_value(value: &mut ConstValue, module: &str) {
+    match *value {
+        ConstValue::List(ref mut vs) => {
+            for v in vs.iter_mut() {
+                absolutize_value(v, module);
+            }
+        }
+        ConstValue::Map(ref mut kvs) => {
+            for &mut (ref mut k, ref mut v) in kvs.iter_mut() {
+                absolutize_value(k, module);
+                absolutize_value(v, module);
+            }
+        }
+        ConstValue::Ident(ref mut name) if name != "true" && name != "false" => absolutize(name, module),
+        _ => (),
+    }
+}
+
+fn absolutize(name: &mut String, module: &str) {
+    if !name.starts_with("super::") {
+        *name = format!("super::{}::{}", module, name);
+    }
+}
+
+fn absolutize_ty(ty: &mut Ty, module: &str) {
+    match *ty {
+        Ty::List(ref mut ty) | Ty::Set(ref mut ty) => absolutize_ty(ty, module),
+        Ty::Map(ref mut key, ref mut value) => {
+            absolutize_ty(key, module);
+            absolutize_ty(value, module);
+        }
+        Ty::Ident(ref mut name) => absolutize(name, module),
+        _ => (),
+    }
+}
+
+#[test]
+fn test_qualify_ty() {
+    let mut includes = HashMap::new();
+    includes.insert("shared".to_string(), "share".to_string());
+    let mut ty = Ty::Map(Box::new(Ty::Ident("Local".to_string())),
+                         Box::new(Ty::List(Box::new(Ty::Ident("shared.Foo".to_string())))));
+    qualify_ty(&mut ty, &includes);
+    assert_eq!(ty,
+               Ty::Map(Box::new(Ty::Ident("Local".to_string())),
+                       Box::new(Ty::List(Box::new(Ty::Ident("super::share::Foo".to_string()))))));
+}
+
+#[test]
+fn test_resolve_extends() {
+    let base = Document::parse("namespace rust base\nservice Base {\n  i32 size();\n}\n").unwrap();
+    let text = "namespace rust store\nservice Store extends base.Base {\n}\nservice Cache extends Store {\n}\n";
+    let mut store = Document::parse(text).unwrap();
+    let mut includes = HashMap::new();
+    includes.insert("base".to_string(), "base".to_string());
+    qualify_document(&mut store, &includes);
+    let mut docs = vec![base, store];
+    resolve_extends(&mut docs).unwrap();
+    let idents: Vec<_> = docs[1].definitions.iter()
+        .filter_map(|def| match *def {
+            Definition::Service(ref s) if s.ident == "Cache" => Some(s),
+            _ => None,
+        })
+        .flat_map(|s| s.ancestors.iter().map(|a| a.ident.clone()))
+        .collect();
+    assert_eq!(idents, vec!["Store".to_string(), "super::base::Base".to_string()]);
+
+    let text = "namespace rust cycle\nservice A extends B {\n}\nservice B extends A {\n}\n";
+    let mut docs = vec![Document::parse(text).unwrap()];
+    assert!(resolve_extends(&mut docs).is_err());
+}
+
+#[test]
+fn test_module_collision() {
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::env;
+
+    let dir = env::temp_dir().join("tokio-thrift-module-collision");
+    for sub in &["a", "b"] {
+        fs::create_dir_all(dir.join(sub)).unwrap();
+    }
+    let write = |name: &str, text: &str| File::create(dir.join(name)).unwrap().write_all(text.as_bytes()).unwrap();
+    write("a/common.thrift", "struct A {\n}\n");
+    write("b/common.thrift", "struct B {\n}\n");
+    write("main.thrift", "include \"a/common.thrift\"\ninclude \"b/common.thrift\"\n");
+    match load(&dir.join("main.thrift"), &[]) {
+        Err(Error::Include(msg)) => assert!(msg.contains("module common"), "{}", msg),
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+
+    write("b/common.thrift", "namespace rust other\nstruct B {\n}\n");
+    match load(&dir.join("main.thrift"), &[]) {
+        Err(Error::Include(msg)) => assert!(msg.contains("two files named common"), "{}", msg),
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+}
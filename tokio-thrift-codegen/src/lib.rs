@@ -4,20 +4,46 @@ extern crate rustc_serialize;
 extern crate nom;
 
 pub mod parser;
+mod include;
+mod scope;
+mod check;
+pub use include::load;
+use std::ascii;
+use std::fmt;
 use std::io::{self, Write};
 use std::collections::BTreeMap;
-use rustc_serialize::Decodable;
+use std::str::from_utf8;
+use std::sync::Arc;
+use rustc_serialize::{Decodable, Encodable};
 use rustc_serialize::json::{self, Json};
-use handlebars::{Handlebars, RenderError, RenderContext, Helper, Context, JsonRender};
+use handlebars::{Handlebars, RenderError, RenderContext, Helper, Context};
 use parser::*;
+use scope::Scope;
 
 
 #[derive(Debug)]
 pub enum Error {
     NotSupported(String),
     IO(io::Error),
+    Parse(String),
+    Include(String),
+    Extends(String),
+    Semantic(String),
     Generate(handlebars::RenderError),
-    Eof,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::NotSupported(ref s) => write!(f, "not supported: {}", s),
+            Error::IO(ref e) => write!(f, "{}", e),
+            Error::Parse(ref s) |
+            Error::Include(ref s) |
+            Error::Extends(ref s) |
+            Error::Semantic(ref s) => write!(f, "{}", s),
+            Error::Generate(ref e) => write!(f, "{}", e),
+        }
+    }
 }
 
 impl From<io::Error> for Error {
@@ -43,16 +69,20 @@ pub fn find_rust_namespace(doc: &Document) -> Option<&Namespace> {
     })
 }
 
+fn ty_param(h: &Helper, helper: &str) -> Result<Ty, RenderError> {
+    let param = h.param(0)
+        .ok_or(RenderError::new(format!("Param 0 is required for {} helper.", helper)))?;
+    let mut decoder = json::Decoder::new(param.value().clone());
+    Ok(Ty::decode(&mut decoder).expect("internal error: failed to decode json type"))
+}
+
 // define a custom helper
 fn helper_ty_to_protocol(_: &Context,
                          h: &Helper,
                          _: &Handlebars,
                          rc: &mut RenderContext)
                          -> Result<(), RenderError> {
-    let param = h.param(0)
-        .ok_or(RenderError::new("Param 0 is required for to_protocol helper."))?;
-    let rendered = param.value().render();
-    let ty = Ty::from(rendered);
+    let ty = ty_param(h, "to_protocol")?;
     let ret = ty.to_protocol();
     rc.writer.write(ret.as_bytes())?;
     Ok(())
@@ -63,44 +93,203 @@ fn helper_ty_to_rust(_: &Context,
                      _: &Handlebars,
                      rc: &mut RenderContext)
                      -> Result<(), RenderError> {
-    let param = h.param(0).ok_or(RenderError::new("Param 0 is required for to_rust helper."))?;
-    let rendered = param.value().render();
-    let ty = Ty::from(rendered);
+    let ty = ty_param(h, "to_rust")?;
     let ret = ty.to_string();
     rc.writer.write(ret.as_bytes())?;
     Ok(())
 }
 
-fn helper_const_to_literal(_: &Context,
-                     h: &Helper,
-                     _: &Handlebars,
-                     rc: &mut RenderContext)
-                     -> Result<(), RenderError> {
+/// The Rust expression building `value` as a `ty`.
+fn value_expr(ty: &Ty, value: &ConstValue, scope: &Scope) -> Result<String, RenderError> {
+    use parser::ConstValue::*;
+    // constants are `const` items if they can be, and functions otherwise
+    if let Ident(ref name) = *value {
+        if let Some(&Definition::Const(ref c)) = scope.find(name) {
+            return Ok(if is_const_item(&c.ty, scope) { name.clone() } else { format!("{}()", name) });
+        }
+    }
+    let expr = match (scope.resolve(ty), value) {
+        (&Ty::Bool, &Int(i)) => (i != 0).to_string(),
+        (&Ty::Bool, &Ident(ref b)) if b == "true" || b == "false" => b.clone(),
+        // signed in the IDL, but held in a `u8`
+        (&Ty::Byte, &Int(i)) => match in_range(i, i8::min_value() as i64, i8::max_value() as i64, "byte")? {
+            i if i < 0 => format!("({}i8) as u8", i),
+            i => format!("{}u8", i),
+        },
+        (&Ty::I8, &Int(i)) => format!("{}i8", in_range(i, i8::min_value() as i64, i8::max_value() as i64, "i8")?),
+        (&Ty::I16, &Int(i)) => format!("{}i16", in_range(i, i16::min_value() as i64, i16::max_value() as i64, "i16")?),
+        (&Ty::I32, &Int(i)) => format!("{}i32", in_range(i, i32::min_value() as i64, i32::max_value() as i64, "i32")?),
+        (&Ty::I64, &Int(i)) => format!("{}i64", i),
+        (&Ty::Double, &Int(i)) => format!("{}f64", i),
+        (&Ty::Double, &Double(d)) => format!("{:?}f64", d),
+        (&Ty::String, &String(ref s)) => format!("{:?}.to_string()", s),
+        (&Ty::Binary, &String(ref s)) => {
+            let bytes: Vec<u8> = s.bytes().flat_map(ascii::escape_default).collect();
+            format!("::tokio_thrift::protocol::Binary(b\"{}\".to_vec())", from_utf8(&bytes).expect("internal error"))
+        }
+        (&Ty::List(ref ty), &List(ref vs)) => format!("vec![{}]", values_expr(ty, vs, scope)?),
+        (&Ty::Set(ref ty), &List(ref vs)) => format!("vec![{}].into_iter().collect()", values_expr(ty, vs, scope)?),
+        (&Ty::Map(ref k, ref v), &Map(ref kvs)) => {
+            let kvs = kvs.iter()
+                .map(|&(ref key, ref val)| Ok(format!("({}, {})", value_expr(k, key, scope)?, value_expr(v, val, scope)?)))
+                .collect::<Result<Vec<_>, RenderError>>()?;
+            format!("vec![{}].into_iter().collect()", kvs.join(", "))
+        }
+        (&Ty::Ident(ref name), _) => user_value_expr(name, value, scope)?,
+        // sets and maps are collected already, into whatever the field holds
+        (&Ty::Custom(_, ref ty), _) => match **ty {
+            Ty::Set(_) | Ty::Map(_, _) => value_expr(ty, value, scope)?,
+            Ty::List(_) => format!("{}.into_iter().collect()", value_expr(ty, value, scope)?),
+            _ => format!("From::from({})", value_expr(ty, value, scope)?),
+        },
+        _ => return Err(RenderError::new(format!("a {:?} value is not supported for {}", value, ty.to_string()))),
+    };
+    Ok(expr)
+}
+
+fn in_range(i: i64, min: i64, max: i64, ty: &str) -> Result<i64, RenderError> {
+    if min <= i && i <= max {
+        Ok(i)
+    } else {
+        Err(RenderError::new(format!("{} is out of range for {}", i, ty)))
+    }
+}
+
+fn values_expr(ty: &Ty, vs: &[ConstValue], scope: &Scope) -> Result<String, RenderError> {
+    let vs = vs.iter().map(|v| value_expr(ty, v, scope)).collect::<Result<Vec<_>, _>>()?;
+    Ok(vs.join(", "))
+}
+
+/// The fields of `name` set by the map `kvs`, with the expressions of their values.
+fn field_values<'a>(name: &str,
+                    fields: &'a [StructField],
+                    kvs: &[(ConstValue, ConstValue)],
+                    scope: &Scope)
+                    -> Result<Vec<(&'a StructField, String)>, RenderError> {
+    kvs.iter().map(|&(ref key, ref val)| {
+        let field = match *key {
+            ConstValue::String(ref key) => fields.iter().find(|f| &f.ident == key),
+            _ => None,
+        };
+        match field {
+            Some(field) if field.optional => Ok((field, format!("Some({})", value_expr(&field.ty, val, scope)?))),
+            Some(field) => Ok((field, value_expr(&field.ty, val, scope)?)),
+            None => Err(RenderError::new(format!("{} has no field {:?}", name, key))),
+        }
+    }).collect()
+}
+
+/// The Rust expression building `value` as the enum, struct, exception or union `name`.
+fn user_value_expr(name: &str, value: &ConstValue, scope: &Scope) -> Result<String, RenderError> {
     use parser::ConstValue::*;
-    let param = h.param(0).ok_or(RenderError::new("Param 0 is required for to_literal helper."))?;
+    let expr = match (scope.find(name), value) {
+        (Some(&Definition::Enum(ref e)), &Int(i)) => {
+            match enum_values(e).into_iter().find(|&(_, value)| value == i) {
+                Some((variant, _)) => format!("{}::{}", name, variant),
+                None => return Err(RenderError::new(format!("{} has no variant {}", name, i))),
+            }
+        }
+        // `Color.RED`, or `super::module::Color.RED` when included
+        (Some(&Definition::Enum(_)), &Ident(ref variant)) => variant.replace(".", "::"),
+        (Some(&Definition::Struct(Struct { ref fields, .. })), &Map(ref kvs)) |
+        (Some(&Definition::Exception(Exception { ref fields, .. })), &Map(ref kvs)) => {
+            let fields = field_values(name, fields, kvs, scope)?.into_iter()
+                .map(|(field, expr)| format!("{}: {}, ", field.rust_ident(), expr))
+                .collect::<Vec<_>>();
+            format!("{} {{ {}..Default::default() }}", name, fields.concat())
+        }
+        (Some(&Definition::Union(ref u)), &Map(ref kvs)) if kvs.len() == 1 => {
+            let (field, expr) = field_values(name, &u.fields, kvs, scope)?.remove(0);
+            format!("{}::{}({})", name, field.rust_ident(), expr)
+        }
+        _ => return Err(RenderError::new(format!("a {:?} value is not supported for {}", value, name))),
+    };
+    Ok(expr)
+}
+
+/// Whether constants of `ty` can be `const` items rather than functions.
+fn is_const_item(ty: &Ty, scope: &Scope) -> bool {
+    match *scope.resolve(ty) {
+        Ty::Bool | Ty::Byte | Ty::I8 | Ty::I16 | Ty::I32 | Ty::I64 | Ty::Double => true,
+        Ty::Ident(ref name) => match scope.find(name) {
+            Some(&Definition::Enum(_)) => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Whether values of `ty` hold an `f64` somewhere, which keeps them from being `Eq`.
+/// Unions are looked into as they derive `Eq` only without one; `seen` stops cycles.
+fn holds_float(ty: &Ty, scope: &Scope, seen: &mut Vec<String>) -> bool {
+    match *scope.resolve(ty) {
+        Ty::Double => true,
+        Ty::List(ref ty) | Ty::Set(ref ty) | Ty::Custom(_, ref ty) => holds_float(ty, scope, seen),
+        Ty::Map(ref key, ref value) => holds_float(key, scope, seen) || holds_float(value, scope, seen),
+        Ty::Ident(ref name) if !seen.contains(name) => match scope.find(name) {
+            Some(&Definition::Union(ref u)) => {
+                seen.push(name.clone());
+                u.fields.iter().any(|field| holds_float(&field.ty, scope, seen))
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// The variants of `e` with their values, counting on from the previous one where
+/// none is given.
+fn enum_values(e: &Enum) -> Vec<(String, i64)> {
+    let mut next = 0;
+    e.variants.iter().map(|variant| {
+        let value = variant.seq.unwrap_or(next);
+        next = value + 1;
+        (variant.ident.clone(), value)
+    }).collect()
+}
+
+/// The value a field takes when it is not given: its IDL default, `None` if it is
+/// optional and has none, and `Default::default()` otherwise.
+fn helper_field_default(h: &Helper, rc: &mut RenderContext, scope: &Scope) -> Result<(), RenderError> {
+    let param = h.param(0).ok_or(RenderError::new("Param 0 is required for default helper."))?;
     let mut decoder = json::Decoder::new(param.value().clone());
-    let v = ConstValue::decode(&mut decoder).expect("internal error: failed to decode json value");
-    let ret = match v {
-        Int(i) => i.to_string(),
-        Double(d) => d.to_string(),
-        String(s) => format!("{:?}", s),
-        List(_) => panic!("list literal is not supported yet"),
-        Map => panic!("map literal is not supported yet"),
+    let field = StructField::decode(&mut decoder).expect("internal error: failed to decode json field");
+    let named = |e: RenderError| RenderError::new(format!("field {}: {}", field.ident, e.desc));
+    let ret = match (field.optional, field.value.as_ref()) {
+        (false, Some(value)) => value_expr(&field.ty, value, scope).map_err(named)?,
+        (true, Some(value)) => format!("Some({})", value_expr(&field.ty, value, scope).map_err(named)?),
+        (false, None) => "Default::default()".to_string(),
+        (true, None) => "None".to_string(),
     };
     rc.writer.write(ret.as_bytes())?;
     Ok(())
 }
 
 
+/// Writes a doc comment as `///` lines, each followed by the indentation given in
+/// spaces so that the documented item lines up under them. Nothing without one.
+fn helper_doc(_: &Context,
+              h: &Helper,
+              _: &Handlebars,
+              rc: &mut RenderContext)
+              -> Result<(), RenderError> {
+    let param = h.param(0).ok_or(RenderError::new("Param 0 is required for doc helper."))?;
+    let indent = h.param(1).and_then(|p| p.value().as_u64()).unwrap_or(0) as usize;
+    if let Some(doc) = param.value().as_string() {
+        for line in doc.lines() {
+            let line = if line.is_empty() { "///".to_string() } else { format!("/// {}", line) };
+            write!(rc.writer, "{}\n{}", line, " ".repeat(indent))?;
+        }
+    }
+    Ok(())
+}
 
 fn helper_ty_expr(_: &Context,
                   h: &Helper,
                   _: &Handlebars,
                   rc: &mut RenderContext)
                   -> Result<(), RenderError> {
-    let param = h.param(0).ok_or(RenderError::new("Param 0 is required for expr helper."))?;
-    let rendered = param.value().render();
-    let ty = Ty::from(rendered);
+    let ty = ty_param(h, "expr")?;
     let expr = match ty {
         Ty::String => "de.deserialize_str()".to_string(),
         Ty::Byte => "de.deserialize_u8()".to_string(),
@@ -109,8 +298,9 @@ fn helper_ty_expr(_: &Context,
         Ty::I64 => "de.deserialize_i64()".to_string(),
         Ty::Bool => "de.deserialize_bool()".to_string(),
         Ty::Double => "de.deserialize_f64()".to_string(),
-        Ty::Binary => "de.deserialize_bytes()".to_string() ,
-        Ty::Ident(s) => format!("{}::deserialize(de)", s) ,
+        Ty::Binary => "de.deserialize_bytes().map(::tokio_thrift::protocol::Binary)".to_string(),
+        Ty::Ident(_) | Ty::List(_) | Ty::Set(_) | Ty::Map(_, _) | Ty::Custom(_, _) =>
+            format!("<{} as ::tokio_thrift::protocol::Deserialize>::deserialize(de)", ty.to_string()),
         _ => panic!("Unexpected type to deserialize_arg: {:?}.", ty),
     };
     rc.writer.write(expr.as_bytes())?;
@@ -130,21 +320,46 @@ macro_rules! static_register_files {
 }
 
 
-pub fn compile(mut doc: Document, wr: &mut Write) -> Result<(), Error> {
+pub fn compile(doc: Document, wr: &mut Write) -> Result<(), Error> {
+    compile_in(doc, &[], wr)
+}
+
+/// Like `compile`, looking up the names `doc` takes from other files in `docs`, the
+/// documents `load` returned along with it.
+pub fn compile_in(mut doc: Document, docs: &[Document], wr: &mut Write) -> Result<(), Error> {
     let mut handlebars = Handlebars::new();
     static_register_files!(handlebars,
                            "service",
                            "service_client",
                            "service_server",
-                           "struct", "enum",
+                           "struct", "union", "enum",
                            "typedef", "const",
-                           "method", "exception");
+                           "method", "client_method", "exception");
 
-    doc.rearrange();
+    // `load` already found the services extended, but not `Document::parse`
+    let unresolved = doc.definitions.iter().any(|def| match *def {
+        Definition::Service(ref s) => s.extends.is_some() && s.ancestors.is_empty(),
+        _ => false,
+    });
+    if unresolved {
+        let mut all = docs.to_vec();
+        all.push(doc);
+        include::resolve_extends(&mut all)?;
+        doc = all.pop().expect("internal error");
+    }
+    doc.rearrange()?;
     handlebars.register_helper("expr", Box::new(helper_ty_expr));
     handlebars.register_helper("to_protocol", Box::new(helper_ty_to_protocol));
     handlebars.register_helper("to_rust", Box::new(helper_ty_to_rust));
-    handlebars.register_helper("to_literal", Box::new(helper_const_to_literal));
+    handlebars.register_helper("doc", Box::new(helper_doc));
+    let scope = Arc::new(Scope::new(&doc, docs));
+    check::check(&doc, &scope)?;
+    {
+        let scope = scope.clone();
+        handlebars.register_helper("default", Box::new(move |_: &Context, h: &Helper, _: &Handlebars, rc: &mut RenderContext| {
+            helper_field_default(h, rc, &scope)
+        }));
+    }
 
 
     let mut data: BTreeMap<String, Json> = BTreeMap::new();
@@ -154,11 +369,11 @@ pub fn compile(mut doc: Document, wr: &mut Write) -> Result<(), Error> {
     for def in doc.definitions.iter() {
         use parser::Definition::*;
         match def {
-            &Const(ref c)     => gen_const    (c, &mut data, wr, &mut handlebars)?,
+            &Const(ref c)     => gen_const    (c, &scope, &mut data, wr, &mut handlebars)?,
             &Typedef(ref t)   => gen_typedef  (t, &mut data, wr, &mut handlebars)?,
             &Enum(ref e)      => gen_enum     (e, &mut data, wr, &mut handlebars)?,
             &Struct(ref s)    => gen_struct   (s, &mut data, wr, &mut handlebars)?,
-            &Union(_)         => return Err(Error::NotSupported("union is not supported yet".to_string())),
+            &Union(ref u)     => gen_union    (u, &scope, &mut data, wr, &mut handlebars)?,
             &Exception(ref e) => gen_exception(e, &mut data, wr, &mut handlebars)?,
             &Service(ref s)   => gen_service  (s, &mut data, wr, &mut handlebars)?,
         }
@@ -166,33 +381,63 @@ pub fn compile(mut doc: Document, wr: &mut Write) -> Result<(), Error> {
     Ok(())
 }
 
-fn gen_enum(enum_: &Enum, data: &mut BTreeMap<String, Json>, wr: &mut Write, handlebars: &mut Handlebars) -> Result<(), Error> {
-    let json = json::encode(enum_)
+fn to_json<T: Encodable>(value: &T) -> Json {
+    json::encode(value)
         .ok()
         .and_then(|s| Json::from_str(&s).ok())
-        .expect("internal error");
+        .expect("internal error")
+}
+
+fn gen_enum(enum_: &Enum, data: &mut BTreeMap<String, Json>, wr: &mut Write, handlebars: &mut Handlebars) -> Result<(), Error> {
+    let json = to_json(enum_);
     data.insert("enum".to_string(), json);
+    let values: Vec<Json> = enum_values(enum_).into_iter().zip(enum_.variants.iter()).map(|((ident, value), variant)| {
+        let mut obj = BTreeMap::new();
+        obj.insert("ident".to_string(), Json::String(ident));
+        obj.insert("value".to_string(), Json::I64(value));
+        obj.insert("doc".to_string(), to_json(&variant.doc));
+        Json::Object(obj)
+    }).collect();
+    match values.first() {
+        Some(first) => data.insert("first".to_string(), first.clone()),
+        None => data.remove("first"),
+    };
+    data.insert("values".to_string(), Json::Array(values));
     write!(wr, "{}", handlebars.render("enum", data)?)?;
     Ok(())
 }
 
 
 fn gen_struct(struct_: &Struct, data: &mut BTreeMap<String, Json>, wr: &mut Write, handlebars: &mut Handlebars) -> Result<(), Error> {
-    let json = json::encode(struct_)
-        .ok()
-        .and_then(|s| Json::from_str(&s).ok())
-        .expect("internal error");
+    let json = to_json(struct_);
     data.insert("struct".to_string(), json);
     println!("{:?}", data);
     write!(wr, "{}", handlebars.render("struct", data)?)?;
     Ok(())
 }
 
+fn gen_union(union: &Union, scope: &Scope, data: &mut BTreeMap<String, Json>, wr: &mut Write, handlebars: &mut Handlebars) -> Result<(), Error> {
+    let json = to_json(union);
+    data.insert("union".to_string(), json);
+    let eq = !union.fields.iter().any(|field| holds_float(&field.ty, scope, &mut vec![union.ident.clone()]));
+    data.insert("eq".to_string(), Json::Boolean(eq));
+    // the variant `Default` picks, which holds a plain value even if declared optional
+    match union.fields.first() {
+        Some(first) => {
+            let first = StructField { optional: false, ..first.clone() };
+            let json = to_json(&first);
+            data.insert("first".to_string(), json);
+        }
+        None => {
+            data.remove("first");
+        }
+    }
+    write!(wr, "{}", handlebars.render("union", data)?)?;
+    Ok(())
+}
+
 fn gen_exception(exception: &Exception, data: &mut BTreeMap<String, Json>, wr: &mut Write, handlebars: &mut Handlebars) -> Result<(), Error> {
-    let json = json::encode(exception)
-        .ok()
-        .and_then(|s| Json::from_str(&s).ok())
-        .expect("internal error");
+    let json = to_json(exception);
     data.insert("exception".to_string(), json);
     println!("{:?}", data);
     write!(wr, "{}", handlebars.render("exception", data)?)?;
@@ -200,10 +445,7 @@ fn gen_exception(exception: &Exception, data: &mut BTreeMap<String, Json>, wr: &
 }
 
 fn gen_typedef(typedef: &Typedef, data: &mut BTreeMap<String, Json>, wr: &mut Write, handlebars: &mut Handlebars) -> Result<(), Error> {
-    let json = json::encode(typedef)
-        .ok()
-        .and_then(|s| Json::from_str(&s).ok())
-        .expect("internal error");
+    let json = to_json(typedef);
     data.insert("typedef".to_string(), json);
     println!("{:?}", data);
     write!(wr, "{}", handlebars.render("typedef", data)?)?;
@@ -211,26 +453,104 @@ fn gen_typedef(typedef: &Typedef, data: &mut BTreeMap<String, Json>, wr: &mut Wr
 }
 
 
-fn gen_const(const_: &Const, data: &mut BTreeMap<String, Json>, wr: &mut Write, handlebars: &mut Handlebars) -> Result<(), Error> {
-    let json = json::encode(const_)
-        .ok()
-        .and_then(|s| Json::from_str(&s).ok())
-        .expect("internal error");
+fn gen_const(const_: &Const, scope: &Scope, data: &mut BTreeMap<String, Json>, wr: &mut Write, handlebars: &mut Handlebars) -> Result<(), Error> {
+    let json = to_json(const_);
     data.insert("const".to_string(), json);
-    println!("{:?}", data);
+    let value = value_expr(&const_.ty, &const_.value, scope)
+        .map_err(|e| RenderError::new(format!("const {}: {}", const_.ident, e.desc)))?;
+    data.insert("value".to_string(), Json::String(value));
+    data.insert("const_item".to_string(), Json::Boolean(is_const_item(&const_.ty, scope)));
     write!(wr, "{}", handlebars.render("const", data)?)?;
     Ok(())
 }
 
 
 fn gen_service(service: &Service, data: &mut BTreeMap<String, Json>, wr: &mut Write, handlebars: &mut Handlebars) -> Result<(), Error> {
-    let json = json::encode(service)
-        .ok()
-        .and_then(|s| Json::from_str(&s).ok())
-        .expect("internal error");
+    let json = to_json(service);
     data.insert("service".to_string(), json);
+
+    // the traits the client implements, its own first, and every method it dispatches
+    let mut traits = Vec::new();
+    let mut all_methods = Vec::new();
+    let mut names = Vec::new();
+    for s in Some(service).into_iter().chain(service.ancestors.iter()) {
+        let path = if s.ident.starts_with("super::") {
+            s.ident.clone()
+        } else {
+            format!("self::{}", s.ident)
+        };
+        let mut methods = Vec::new();
+        for method in s.methods.iter() {
+            let mut json = to_json(method);
+            if let Json::Object(ref mut obj) = json {
+                obj.insert("path".to_string(), Json::String(path.clone()));
+                obj.insert("void".to_string(), Json::Boolean(method.ty == Ty::Void));
+            }
+            // a method redeclared further down the chain hides the parent's one
+            if !names.contains(&method.ident) {
+                names.push(method.ident.clone());
+                all_methods.push(json.clone());
+            }
+            methods.push(json);
+        }
+        let mut obj = BTreeMap::new();
+        obj.insert("path".to_string(), Json::String(path));
+        obj.insert("methods".to_string(), Json::Array(methods));
+        traits.push(Json::Object(obj));
+    }
+    data.insert("traits".to_string(), Json::Array(traits));
+    data.insert("all_methods".to_string(), Json::Array(all_methods));
+
     write!(wr, "{}", handlebars.render("service", data)?)?;
     write!(wr, "{}", handlebars.render("service_client", data)?)?;
     write!(wr, "{}", handlebars.render("service_server", data)?)?;
     Ok(())
 }
+
+#[test]
+fn test_doc() {
+    let text = "/**\n * A point.\n *\n * In the plane.\n */\nstruct Point {\n  /** Abscissa */\n  1: i32 x;\n}\n\
+                /** Answers. */\nservice Geometry {\n  /** The origin. */\n  Point origin();\n}\n";
+    let mut out = Vec::new();
+    compile(Document::parse(text).unwrap(), &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("/// A point.\n///\n/// In the plane.\n#[allow"), "{}", out);
+    assert!(out.contains("    /// Abscissa\n    #[allow(dead_code, non_snake_case)]\n    pub x: i32,"), "{}", out);
+    assert!(out.contains("/// Answers.\n#[allow"), "{}", out);
+    assert!(out.contains("    /// The origin.\n    #[allow"), "{}", out);
+}
+
+#[test]
+fn test_local_extends() {
+    let text = "service Base {\n  i32 size();\n}\nservice Store extends Base {\n  void clear();\n}\n";
+    let mut out = Vec::new();
+    compile(Document::parse(text).unwrap(), &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("impl <S>self::BaseService for StoreClient<S>"), "{}", out);
+    assert!(out.contains("StoreServiceMethodArgs::Asize("), "{}", out);
+
+    let text = "service Store extends Missing {\n}\n";
+    let mut out = Vec::new();
+    assert!(compile(Document::parse(text).unwrap(), &mut out).is_err());
+}
+
+#[test]
+fn test_int_range() {
+    let generate = |text: &str| {
+        let mut out = Vec::new();
+        compile(Document::parse(text).unwrap(), &mut out).map(|_| String::from_utf8(out).unwrap())
+    };
+    let out = generate("const byte A = -1\nconst i16 B = -32768\n").unwrap();
+    assert!(out.contains("(-1i8) as u8"), "{}", out);
+    assert!(out.contains("-32768i16"), "{}", out);
+
+    match generate("const i8 A = 128\n") {
+        Err(Error::Generate(e)) => assert_eq!(e.desc, "const A: 128 is out of range for i8"),
+        ret => panic!("unexpected result {:?}", ret),
+    }
+    match generate("struct Foo {\n  1: i32 bar = 2147483648;\n}\n") {
+        // the error may also tell where the template was at
+        Err(Error::Generate(e)) => assert!(e.desc.contains("field bar: 2147483648 is out of range for i32"), "{}", e),
+        ret => panic!("unexpected result {:?}", ret),
+    }
+}
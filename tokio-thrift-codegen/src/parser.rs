@@ -1,7 +1,10 @@
 extern crate rustc_serialize;
 use rustc_serialize::{Decodable, Encodable, Decoder, Encoder};
+use std::fmt;
+use std::mem;
 use std::str::from_utf8;
-use nom::{alpha, digit, multispace, eof, IResult, Err};
+use nom::{alpha, digit, multispace, eof, IResult, Err, ErrorKind};
+use super::Error;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Ty {
@@ -19,7 +22,10 @@ pub enum Ty {
     Set(Box<Ty>),
     Map(Box<Ty>, Box<Ty>),
     // User-defined type.
-    Ident(String)
+    Ident(String),
+    /// A Rust type given by a `rust.type` annotation, like `BTreeMap`, standing for
+    /// the Thrift type. Type parameters are added unless it has its own.
+    Custom(String, Box<Ty>),
 }
 
 impl From<String> for Ty {
@@ -42,8 +48,34 @@ impl From<String> for Ty {
 }
 
 impl Decodable for Ty {
-    fn decode<D: Decoder>(_d: &mut D) -> Result<Self, D::Error> {
-        unimplemented!()
+    fn decode<D: Decoder>(d: &mut D) -> Result<Self, D::Error> {
+        use self::Ty::*;
+        let names = ["string", "void", "byte", "bool", "binary", "i8", "i16", "i32", "i64", "double",
+                     "list", "set", "map", "ident", "custom"];
+        d.read_enum("Ty", |d| {
+            d.read_enum_variant(&names, |d, i| {
+                Ok(match i {
+                    0 => String,
+                    1 => Void,
+                    2 => Byte,
+                    3 => Bool,
+                    4 => Binary,
+                    5 => I8,
+                    6 => I16,
+                    7 => I32,
+                    8 => I64,
+                    9 => Double,
+                    10 => List(Box::new(try!(d.read_enum_variant_arg(0, Ty::decode)))),
+                    11 => Set(Box::new(try!(d.read_enum_variant_arg(0, Ty::decode)))),
+                    12 => Map(Box::new(try!(d.read_enum_variant_arg(0, Ty::decode))),
+                              Box::new(try!(d.read_enum_variant_arg(1, Ty::decode)))),
+                    13 => Ident(try!(d.read_enum_variant_arg(0, |d| d.read_str()))),
+                    14 => Custom(try!(d.read_enum_variant_arg(0, |d| d.read_str())),
+                                 Box::new(try!(d.read_enum_variant_arg(1, Ty::decode)))),
+                    _ => return Err(d.error("unknown type")),
+                })
+            })
+        })
     }
 }
 
@@ -90,6 +122,15 @@ impl Encodable for Ty {
                     }));
                     Ok(())
                 }),
+                &Custom(ref string, ref ty) => s.emit_enum_variant("custom", 15, 2, |s| {
+                    try!(s.emit_enum_variant_arg(0, |s| {
+                        s.emit_str(&string)
+                    }));
+                    try!(s.emit_enum_variant_arg(1, |s| {
+                        ty.encode(s)
+                    }));
+                    Ok(())
+                }),
             }
         })
     }
@@ -109,8 +150,10 @@ impl Ty {
             &Ty::Map(_, _) => "::tokio_thrift::protocol::ThriftType::Map".to_string(),
             &Ty::List(_) => "::tokio_thrift::protocol::ThriftType::List".to_string(),
             &Ty::Set(_) => "::tokio_thrift::protocol::ThriftType::Set".to_string(),
-            &Ty::Binary => "::tokio_thrift::protocol::ThriftType::List".to_string(),
-            &Ty::Ident(ref s) => s.to_string(),
+            &Ty::Binary => "::tokio_thrift::protocol::ThriftType::String".to_string(),
+            // user-defined types know their own wire type
+            &Ty::Ident(ref s) => format!("<{} as ::tokio_thrift::protocol::Serialize>::thrift_type()", s),
+            &Ty::Custom(_, ref ty) => ty.to_protocol(),
             t => panic!("Not compatible with ThriftType: {:?}", t)
         }
     }
@@ -121,45 +164,51 @@ impl Ty {
             &Ty::Void => "()".to_string(),
             &Ty::Byte => "u8".to_string(),
             &Ty::Bool => "bool".to_string(),
-            &Ty::Binary => "Vec<i8>".to_string(),
+            &Ty::Binary => "::tokio_thrift::protocol::Binary".to_string(),
             &Ty::I8 => "i8".to_string(),
             &Ty::I16 => "i16".to_string(),
             &Ty::I32 => "i32".to_string(),
             &Ty::I64 => "i64".to_string(),
-            &Ty::Double => "double".to_string(),
+            &Ty::Double => "f64".to_string(),
             &Ty::List(ref s) => {
                 let inner = s.to_string();
                 format!("Vec<{}>", inner)
             },
             &Ty::Set(ref s) => {
                 let inner = s.to_string();
-                format!("HashSet<{}>", inner)
+                format!("::std::collections::HashSet<{}>", inner)
             },
             &Ty::Map(ref a, ref b) => {
                 let a = a.to_string();
                 let b = b.to_string();
-                format!("HashMap<{}, {}>", a, b)
+                format!("::std::collections::HashMap<{}, {}>", a, b)
             },
             &Ty::Ident(ref s) => {
                 s.clone()
             }
+            &Ty::Custom(ref s, _) if s.contains('<') => s.clone(),
+            &Ty::Custom(ref s, ref ty) => match **ty {
+                Ty::List(ref v) | Ty::Set(ref v) => format!("{}<{}>", s, v.to_string()),
+                Ty::Map(ref k, ref v) => format!("{}<{}, {}>", s, k.to_string(), v.to_string()),
+                _ => s.clone(),
+            },
         }
     }
 }
 
-#[derive(Debug, PartialEq, RustcEncodable, RustcDecodable)]
+#[derive(Debug, PartialEq, Clone, RustcEncodable, RustcDecodable)]
 pub struct Document {
     pub headers: Vec<Header>,
     pub definitions: Vec<Definition>,
 }
 
-#[derive(Debug, PartialEq, RustcEncodable, RustcDecodable)]
+#[derive(Debug, PartialEq, Clone, RustcEncodable, RustcDecodable)]
 pub enum Header {
     Include(Include),
     Namespace(Namespace),
 }
 
-#[derive(Debug, PartialEq, RustcEncodable, RustcDecodable)]
+#[derive(Debug, PartialEq, Clone, RustcEncodable, RustcDecodable)]
 pub enum Definition {
     Const(Const),
     Typedef(Typedef),
@@ -170,122 +219,459 @@ pub enum Definition {
     Service(Service),
 }
 
-#[derive(Debug, PartialEq, RustcEncodable, RustcDecodable)]
+impl Definition {
+    pub fn ident(&self) -> &str {
+        match *self {
+            Definition::Const(ref c) => &c.ident,
+            Definition::Typedef(ref t) => &t.ident,
+            Definition::Enum(ref e) => &e.ident,
+            Definition::Struct(ref s) => &s.ident,
+            Definition::Union(ref u) => &u.ident,
+            Definition::Exception(ref e) => &e.ident,
+            Definition::Service(ref s) => &s.ident,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, RustcEncodable, RustcDecodable)]
 pub struct Include {
     pub path: String,
 }
 
-#[derive(Debug, PartialEq, RustcEncodable, RustcDecodable)]
+#[derive(Debug, PartialEq, Clone, RustcEncodable, RustcDecodable)]
 pub struct Service {
     pub extends: Option<String>,
     pub ident: String,
     pub methods: Vec<ServiceMethod>,
+    pub annotations: Vec<Annotation>,
+    /// The text of the `/** */` comment right before it.
+    pub doc: Option<String>,
+    /// The services this one extends, nearest first, as filled in by `load`.
+    pub ancestors: Vec<Service>,
+}
+
+impl Service {
+    /// Arguments are never optional, like with Apache's compiler, which ignores the
+    /// keyword there.
+    fn ignore_optional_args(&mut self) {
+        for method in self.methods.iter_mut() {
+            for arg in method.args.iter_mut() {
+                arg.optional = false;
+            }
+        }
+    }
+
+    fn assign_field_ids(&mut self) -> Result<(), Error> {
+        for method in self.methods.iter_mut() {
+            assign_field_ids(&format!("arguments of {}.{}", self.ident, method.ident), &mut method.args)?;
+            if let Some(ref mut throws) = method.throws {
+                assign_field_ids(&format!("exceptions of {}.{}", self.ident, method.ident), throws)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_rust_types(&mut self) {
+        for method in self.methods.iter_mut() {
+            apply_rust_type(&mut method.ty, &method.annotations);
+            apply_rust_types(&mut method.args);
+        }
+    }
 }
 
-#[derive(Debug, PartialEq, RustcEncodable, RustcDecodable)]
+/// Encodes with `ident` being the Rust name, and `name` the IDL one, going on the wire.
+#[derive(Debug, PartialEq, Clone, RustcDecodable)]
 pub struct ServiceMethod {
     pub oneway: bool,
     pub ident: String,
     pub ty: Ty,
     pub args: Vec<StructField>,
     pub throws: Option<Vec<StructField>>,
+    /// Those of the method, and of its return type.
+    pub annotations: Vec<Annotation>,
+    pub doc: Option<String>,
+}
+
+impl ServiceMethod {
+    /// The name of the method in Rust: its `rust.name` annotation, or its IDL name.
+    pub fn rust_ident(&self) -> &str {
+        annotation(&self.annotations, "rust.name").unwrap_or(&self.ident)
+    }
+}
+
+impl Encodable for ServiceMethod {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_struct("ServiceMethod", 8, |s| {
+            try!(s.emit_struct_field("oneway", 0, |s| self.oneway.encode(s)));
+            try!(s.emit_struct_field("ident", 1, |s| s.emit_str(self.rust_ident())));
+            try!(s.emit_struct_field("name", 2, |s| s.emit_str(&self.ident)));
+            try!(s.emit_struct_field("ty", 3, |s| self.ty.encode(s)));
+            try!(s.emit_struct_field("args", 4, |s| self.args.encode(s)));
+            try!(s.emit_struct_field("throws", 5, |s| self.throws.encode(s)));
+            try!(s.emit_struct_field("annotations", 6, |s| self.annotations.encode(s)));
+            s.emit_struct_field("doc", 7, |s| self.doc.encode(s))
+        })
+    }
 }
 
-#[derive(Debug, PartialEq, RustcEncodable, RustcDecodable)]
+#[derive(Debug, PartialEq, Clone, RustcEncodable, RustcDecodable)]
 pub struct Enum {
     pub ident: String,
     pub variants: Vec<Variant>,
+    pub annotations: Vec<Annotation>,
+    pub doc: Option<String>,
 }
 
 
-#[derive(Debug, PartialEq, RustcEncodable, RustcDecodable)]
+#[derive(Debug, PartialEq, Clone, RustcEncodable, RustcDecodable)]
 pub struct Variant {
     pub ident: String,
     pub seq: Option<i64>,
+    pub annotations: Vec<Annotation>,
+    pub doc: Option<String>,
 }
 
 
-#[derive(Debug, PartialEq, RustcEncodable, RustcDecodable)]
+#[derive(Debug, PartialEq, Clone, RustcEncodable, RustcDecodable)]
 pub struct Union {
     pub ident: String,
     pub fields: Vec<StructField>,
+    pub annotations: Vec<Annotation>,
+    pub doc: Option<String>,
 }
 
 
-#[derive(Debug, PartialEq, RustcEncodable, RustcDecodable)]
+#[derive(Debug, PartialEq, Clone, RustcEncodable, RustcDecodable)]
 pub struct Struct {
     pub ident: String,
     pub fields: Vec<StructField>,
+    pub annotations: Vec<Annotation>,
+    pub doc: Option<String>,
 }
 
-#[derive(Debug, PartialEq, RustcEncodable, RustcDecodable)]
+#[derive(Debug, PartialEq, Clone, RustcEncodable, RustcDecodable)]
 pub struct Exception {
     pub ident: String,
     pub fields: Vec<StructField>,
+    pub annotations: Vec<Annotation>,
+    pub doc: Option<String>,
 }
 
-#[derive(Debug, PartialEq, RustcEncodable, RustcDecodable)]
+/// Encodes with `ident` being the Rust name, and `name` the IDL one, going on the wire.
+#[derive(Debug, PartialEq, Clone, RustcDecodable)]
 pub struct StructField {
     pub seq: Option<i64>,
+    /// Declared `required`, so reading fails without it.
+    pub required: bool,
+    /// Declared `optional`, so it may be left unset. Fields declared neither have the
+    /// default requiredness: always written, and read back as their default if absent.
     pub optional: bool,
     pub ty: Ty,
     pub ident: String,
     pub value: Option<ConstValue>,
+    /// Those of the field, and of its type.
+    pub annotations: Vec<Annotation>,
+    pub doc: Option<String>,
+}
+
+impl StructField {
+    /// The name of the field in Rust: its `rust.name` annotation, or its IDL name.
+    pub fn rust_ident(&self) -> &str {
+        annotation(&self.annotations, "rust.name").unwrap_or(&self.ident)
+    }
+}
+
+impl Encodable for StructField {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_struct("StructField", 9, |s| {
+            try!(s.emit_struct_field("seq", 0, |s| self.seq.encode(s)));
+            try!(s.emit_struct_field("required", 1, |s| self.required.encode(s)));
+            try!(s.emit_struct_field("optional", 2, |s| self.optional.encode(s)));
+            try!(s.emit_struct_field("ty", 3, |s| self.ty.encode(s)));
+            try!(s.emit_struct_field("ident", 4, |s| s.emit_str(self.rust_ident())));
+            try!(s.emit_struct_field("name", 5, |s| s.emit_str(&self.ident)));
+            try!(s.emit_struct_field("value", 6, |s| self.value.encode(s)));
+            try!(s.emit_struct_field("annotations", 7, |s| self.annotations.encode(s)));
+            s.emit_struct_field("doc", 8, |s| self.doc.encode(s))
+        })
+    }
 }
 
-#[derive(Debug, PartialEq, RustcEncodable, RustcDecodable)]
+#[derive(Debug, PartialEq, Clone, RustcEncodable, RustcDecodable)]
 pub struct Typedef {
     pub ty: Ty,
     pub ident: String,
+    /// Those of the typedef, and of its type.
+    pub annotations: Vec<Annotation>,
+}
+
+/// A `key = "value"` pair, in parentheses after what it annotates. A key alone has
+/// the value `"1"`.
+#[derive(Debug, PartialEq, Clone, RustcEncodable, RustcDecodable)]
+pub struct Annotation {
+    pub key: String,
+    pub value: String,
+}
+
+/// The value of the last annotation with `key`.
+pub fn annotation<'a>(annotations: &'a [Annotation], key: &str) -> Option<&'a str> {
+    annotations.iter().rev().find(|a| a.key == key).map(|a| &a.value[..])
 }
 
-#[derive(Debug, PartialEq, RustcEncodable, RustcDecodable)]
+#[derive(Debug, PartialEq, Clone, RustcEncodable, RustcDecodable)]
 pub struct Const {
     pub ident: String,
     pub ty: Ty,
     pub value: ConstValue,
 }
 
-#[derive(Debug, PartialEq, RustcEncodable, RustcDecodable)]
+#[derive(Debug, PartialEq, Clone, RustcEncodable, RustcDecodable)]
 pub enum ConstValue {
     Int(i64),
     Double(f64),
     String(String),
     List(Vec<ConstValue>),
-    // not yet
-    Map,
+    Map(Vec<(ConstValue, ConstValue)>),
+    /// A constant, an enum variant like `Color.RED`, or `true` and `false`.
+    Ident(String),
 }
 
 
-#[derive(Debug, PartialEq, RustcEncodable, RustcDecodable)]
+#[derive(Debug, PartialEq, Clone, RustcEncodable, RustcDecodable)]
 pub struct Namespace {
     pub lang: String,
     pub module: String,
 }
 
 impl Document {
-    pub fn parse(input: &str) -> Result<Option<Self>, Err<&[u8], u32>> {
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
         match document(input.as_bytes()) {
-            IResult::Done(i, d) => {
-                println!("{}", from_utf8(i).unwrap());
-                Ok(Some(d))
-            },
-            IResult::Incomplete(_) => Ok(None),
-            IResult::Error(e) => Err(e),
+            IResult::Done(_, d) => Ok(d),
+            IResult::Incomplete(_) => Err(ParseError::new(input, input.len(), "unexpected end of file".to_string())),
+            IResult::Error(e) => {
+                let (rest, message) = match last_expected(&e) {
+                    Some((rest, code)) => (rest, format!("expected {}", expected(code))),
+                    None => (input.as_bytes(), "syntax error".to_string()),
+                };
+                Err(ParseError::new(input, input.len() - rest.len(), message))
+            }
         }
     }
 
-    pub fn rearrange(&mut self) {
+    pub fn rearrange(&mut self) -> Result<(), Error> {
         // resolve `include`, field id, oneway and void, warn about unsupported feature and so on.
+        for def in self.definitions.iter_mut() {
+            match *def {
+                Definition::Typedef(ref mut t) => apply_rust_type(&mut t.ty, &t.annotations),
+                Definition::Struct(ref mut s) => {
+                    assign_field_ids(&format!("struct {}", s.ident), &mut s.fields)?;
+                    apply_rust_types(&mut s.fields);
+                }
+                Definition::Union(ref mut u) => {
+                    assign_field_ids(&format!("union {}", u.ident), &mut u.fields)?;
+                    apply_rust_types(&mut u.fields);
+                }
+                Definition::Exception(ref mut e) => {
+                    assign_field_ids(&format!("exception {}", e.ident), &mut e.fields)?;
+                    apply_rust_types(&mut e.fields);
+                }
+                Definition::Service(ref mut service) => {
+                    service.ignore_optional_args();
+                    service.assign_field_ids()?;
+                    service.apply_rust_types();
+                    for ancestor in service.ancestors.iter_mut() {
+                        ancestor.ignore_optional_args();
+                        ancestor.assign_field_ids()?;
+                        ancestor.apply_rust_types();
+                    }
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Makes the type of what has a `rust.type` annotation that Rust type.
+fn apply_rust_type(ty: &mut Ty, annotations: &[Annotation]) {
+    if let Some(name) = annotation(annotations, "rust.type") {
+        let thrift = mem::replace(ty, Ty::Void);
+        *ty = Ty::Custom(name.to_string(), Box::new(thrift));
     }
 }
 
+fn apply_rust_types(fields: &mut [StructField]) {
+    for field in fields.iter_mut() {
+        apply_rust_type(&mut field.ty, &field.annotations);
+    }
+}
 
+/// Gives the fields without an id the negative ones Apache's compiler does, -1 for the
+/// first such field and so on, and checks that ids and names are unique and ids fit
+/// in an `i16`. `owner` names the fields' definition in errors.
+fn assign_field_ids(owner: &str, fields: &mut Vec<StructField>) -> Result<(), Error> {
+    let mut implicit = 0;
+    for field in fields.iter_mut() {
+        if field.seq.is_none() {
+            implicit -= 1;
+            field.seq = Some(implicit);
+        }
+    }
+    for (i, field) in fields.iter().enumerate() {
+        let seq = field.seq.unwrap_or(0);
+        if seq < i16::MIN as i64 || seq > i16::MAX as i64 {
+            return Err(Error::Semantic(format!("{}: id {} of field {} does not fit in an i16", owner, seq, field.ident)));
+        }
+        for other in fields[..i].iter() {
+            if other.seq == field.seq {
+                return Err(Error::Semantic(format!("{}: fields {} and {} both have id {}", owner, other.ident, field.ident, seq)));
+            }
+            if other.ident == field.ident {
+                return Err(Error::Semantic(format!("{}: field {} is declared twice", owner, field.ident)));
+            }
+        }
+    }
+    Ok(())
+}
+
+
+/// Where and why a document failed to parse.
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    /// Starting at 1, like `column`.
+    pub line: usize,
+    pub column: usize,
+    /// The whole line the error is on.
+    pub text: String,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(input: &str, offset: usize, message: String) -> ParseError {
+        let start = input[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let end = input[offset..].find('\n').map(|i| offset + i).unwrap_or(input.len());
+        ParseError {
+            line: input[..start].matches('\n').count() + 1,
+            column: input[start..offset].chars().count() + 1,
+            text: input[start..end].trim_right_matches('\r').to_string(),
+            message: message,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // keep tabs so the caret lines up with the text above it
+        let indent: String = self.text.chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        write!(f, "{}:{}: {}\n{}\n{}^", self.line, self.column, self.message, self.text, indent)
+    }
+}
+
+// What a parser failing in `expect!` was looking for, as `ErrorKind::Custom` codes.
+const IDENTIFIER: u32 = 0;
+const TYPE: u32 = 1;
+const BASE_OR_CONTAINER_TYPE: u32 = 2;
+const VALUE: u32 = 3;
+const LITERAL: u32 = 4;
+const EQUALS: u32 = 5;
+const COLON: u32 = 6;
+const COMMA: u32 = 7;
+const CLOSE_ANGLE: u32 = 8;
+const OPEN_BRACE: u32 = 9;
+const OPEN_PAREN: u32 = 10;
+const FIELD_OR_CLOSE_BRACE: u32 = 11;
+const VARIANT_OR_CLOSE_BRACE: u32 = 12;
+const FUNCTION_OR_CLOSE_BRACE: u32 = 13;
+const ARGUMENT_OR_CLOSE_PAREN: u32 = 14;
+const EXCEPTION_OR_CLOSE_PAREN: u32 = 15;
+const VALUE_OR_CLOSE_BRACKET: u32 = 16;
+const ENTRY_OR_CLOSE_BRACE: u32 = 17;
+const DEFINITION: u32 = 18;
+const ANNOTATION_OR_CLOSE_PAREN: u32 = 19;
+
+fn expected(code: u32) -> &'static str {
+    match code {
+        IDENTIFIER => "an identifier",
+        TYPE => "a type",
+        BASE_OR_CONTAINER_TYPE => "a base or container type",
+        VALUE => "a constant value",
+        LITERAL => "a string literal",
+        EQUALS => "`=`",
+        COLON => "`:`",
+        COMMA => "`,`",
+        CLOSE_ANGLE => "`>`",
+        OPEN_BRACE => "`{`",
+        OPEN_PAREN => "`(`",
+        FIELD_OR_CLOSE_BRACE => "a field or `}`",
+        VARIANT_OR_CLOSE_BRACE => "a variant or `}`",
+        FUNCTION_OR_CLOSE_BRACE => "a function or `}`",
+        ARGUMENT_OR_CLOSE_PAREN => "an argument or `)`",
+        EXCEPTION_OR_CLOSE_PAREN => "an exception or `)`",
+        VALUE_OR_CLOSE_BRACKET => "a constant value or `]`",
+        ENTRY_OR_CLOSE_BRACE => "a map entry or `}`",
+        DEFINITION => "a definition",
+        ANNOTATION_OR_CLOSE_PAREN => "an annotation or `)`",
+        _ => "something else",
+    }
+}
+
+/// The innermost `expect!` that failed, with the input it failed on.
+fn last_expected<'a>(e: &Err<&'a [u8], u32>) -> Option<(&'a [u8], u32)> {
+    match *e {
+        Err::NodePosition(ErrorKind::Custom(code), rest, ref next) => last_expected(next).or(Some((rest, code))),
+        Err::NodePosition(_, _, ref next) | Err::Node(_, ref next) => last_expected(next),
+        _ => None,
+    }
+}
+
+fn is_expected(e: &Err<&[u8], u32>) -> bool {
+    match *e {
+        Err::NodePosition(ErrorKind::Custom(_), _, _) => true,
+        _ => false,
+    }
+}
+
+// Fails the enclosing parser, backtracking no further, when the given one does. Used
+// once what came before leaves no other way to read the input.
+macro_rules! expect (
+    ($i:expr, $code:expr, $submac:ident!( $($args:tt)* )) => (
+        error!($i, ErrorKind::Custom($code), complete!($submac!($($args)*)))
+    );
+    ($i:expr, $code:expr, $f:expr) => (
+        expect!($i, $code, call!($f))
+    );
+);
+
+// Passes on to the enclosing parser a failed `expect!` of the given one, which would
+// otherwise be taken by `alt!` or `many0!` to mean the input is something else.
+macro_rules! committed (
+    ($i:expr, $submac:ident!( $($args:tt)* )) => (
+        match $submac!($i, $($args)*) {
+            IResult::Error(e) => {
+                if is_expected(&e) {
+                    return IResult::Error(e);
+                }
+                IResult::Error(e)
+            }
+            res => res,
+        }
+    );
+    ($i:expr, $f:expr) => (
+        committed!($i, call!($f))
+    );
+);
+
+// Definitions take the blanks before them, for their doc comment, and the keywords
+// starting them are `complete!`, so that blanks ending the file are not read as a
+// truncated definition.
 named!(document <Document>, chain!(
-    blank? ~
-        headers: many0!(chain!(h: header ~ blank?, || h)) ~
-        defs:  many0!(chain!(d: definition ~ blank?, || d)) ~
-        eof
+    headers: many0!(committed!(chain!(blank? ~ h: header, || h))) ~
+        defs: many0!(committed!(definition)) ~
+        blank? ~
+        expect!(DEFINITION, eof)
         ,
     || Document {
         headers: headers,
@@ -293,40 +679,41 @@ named!(document <Document>, chain!(
     }));
 
 named!(header <Header>, alt!(
-    include    => {Header::Include} |
-    namespace  => {Header::Namespace}));
+    committed!(include)    => {Header::Include} |
+    committed!(namespace)  => {Header::Namespace}));
 
 named!(include <Include>, chain!(
-    tag!("include") ~ blank ~
-        file: literal,
+    complete!(tag!("include")) ~ blank ~
+        file: expect!(LITERAL, literal),
     || Include{
         path: file,
     }));
 
 named!(namespace <Namespace>, chain!(
-    tag!("namespace") ~ blank ~
-        lang: identifier ~ blank ~
-        ns: identifier,
+    complete!(tag!("namespace")) ~ blank ~
+        lang: expect!(IDENTIFIER, identifier) ~ blank ~
+        ns: expect!(IDENTIFIER, identifier),
     || Namespace{
         lang: lang,
         module: ns,
     }));
 named!(definition <Definition>, alt!(
-    const_    => {Definition::Const}|
-    typedef   => {Definition::Typedef}|
-    enum_     => {Definition::Enum}|
-    struct_   => {Definition::Struct}|
-    union     => {Definition::Union}|
-    exception => {Definition::Exception}|
-    service   => {Definition::Service}));
+    committed!(const_)    => {Definition::Const}|
+    committed!(typedef)   => {Definition::Typedef}|
+    committed!(enum_)     => {Definition::Enum}|
+    committed!(struct_)   => {Definition::Struct}|
+    committed!(union)     => {Definition::Union}|
+    committed!(exception) => {Definition::Exception}|
+    committed!(service)   => {Definition::Service}));
 
 named!(const_ <Const>, chain!(
-    tag!("const") ~ blank ~
-        ty: field_type ~ blank ~
-        id: identifier ~ blank? ~
-        tag!("=") ~ blank? ~
-        value: const_value ~ blank? ~
-        list_separator?,
+    blank? ~
+        complete!(tag!("const")) ~ blank ~
+        ty: expect!(TYPE, field_type) ~ annotations ~ blank ~
+        id: expect!(IDENTIFIER, identifier) ~ blank? ~
+        expect!(EQUALS, tag!("=")) ~ blank? ~
+        value: expect!(VALUE, const_value) ~
+        complete!(chain!(blank? ~ list_separator, ||()))?,
     || Const {
         ident: id,
         ty: ty,
@@ -334,118 +721,173 @@ named!(const_ <Const>, chain!(
     }));
 
 named!(typedef <Typedef>, chain!(
-    tag!("typedef") ~ blank ~
-        ty: definition_type ~ blank ~
-        id: identifier,
+    blank? ~
+        complete!(tag!("typedef")) ~ blank ~
+        ty: expect!(BASE_OR_CONTAINER_TYPE, definition_type) ~
+        ty_annotations: annotations ~ blank ~
+        id: expect!(IDENTIFIER, identifier) ~
+        annotations: annotations,
     || Typedef{
         ty: ty,
         ident: id,
+        annotations: ty_annotations.into_iter().chain(annotations).collect(),
     }));
 
 named!(enum_ <Enum>, chain!(
-    tag!("enum") ~ blank ~
-        id: identifier ~ blank? ~
-        tag!("{") ~ blank? ~
+    doc: doc_comment ~
+        complete!(tag!("enum")) ~ blank ~
+        id: expect!(IDENTIFIER, identifier) ~ blank? ~
+        expect!(OPEN_BRACE, tag!("{")) ~
         variants: many0!(chain!(
-            variant: identifier ~
+            doc: doc_comment ~
+                variant: identifier ~
                 index: chain!(
                     blank? ~
                         tag!("=") ~
                         blank? ~
                         idx: int_constant, || idx)? ~
-                blank? ~
-                list_separator? ~
-                blank? ,
-            || Variant{ident: variant, seq: index})) ~
-            tag!("}"),
+                annotations: annotations ~
+                chain!(blank? ~ list_separator, ||())? ,
+            || Variant{ident: variant, seq: index, annotations: annotations, doc: doc})) ~
+            blank? ~
+            expect!(VARIANT_OR_CLOSE_BRACE, tag!("}")) ~
+        annotations: annotations,
     || Enum{
         ident: id,
         variants: variants,
+        annotations: annotations,
+        doc: doc,
     }));
 
 named!(struct_ <Struct>, chain!(
-    tag!("struct") ~ blank ~
-        id: identifier ~ blank? ~
-        tag!("{") ~
-        fields: many0!(chain!(blank? ~ f: field, || f)) ~
+    doc: doc_comment ~
+        complete!(tag!("struct")) ~ blank ~
+        id: expect!(IDENTIFIER, identifier) ~ blank? ~
+        expect!(OPEN_BRACE, tag!("{")) ~
+        fields: many0!(committed!(field)) ~
         blank? ~
-        tag!("}") ,
+        expect!(FIELD_OR_CLOSE_BRACE, tag!("}")) ~
+        annotations: annotations,
     || Struct {
         ident: id,
         fields: fields,
+        annotations: annotations,
+        doc: doc,
     }));
 
 named!(union <Union>, chain!(
-    tag!("union") ~ blank ~
-        id: identifier ~ blank? ~
-        tag!("{") ~
-        fields: many0!(chain!(blank? ~ f: field, || f)) ~
+    doc: doc_comment ~
+        complete!(tag!("union")) ~ blank ~
+        id: expect!(IDENTIFIER, identifier) ~ blank? ~
+        expect!(OPEN_BRACE, tag!("{")) ~
+        fields: many0!(committed!(field)) ~
         blank? ~
-        tag!("}") ,
+        expect!(FIELD_OR_CLOSE_BRACE, tag!("}")) ~
+        annotations: annotations,
     || Union {
         ident: id,
         fields: fields,
+        annotations: annotations,
+        doc: doc,
     }));
 
 named!(exception <Exception>, chain!(
-    tag!("exception") ~ blank ~
-        id: identifier ~ blank? ~
-        tag!("{") ~
-        fields: many0!(chain!(blank? ~ f: field, || f)) ~
+    doc: doc_comment ~
+        complete!(tag!("exception")) ~ blank ~
+        id: expect!(IDENTIFIER, identifier) ~ blank? ~
+        expect!(OPEN_BRACE, tag!("{")) ~
+        fields: many0!(committed!(field)) ~
         blank? ~
-        tag!("}") ,
+        expect!(FIELD_OR_CLOSE_BRACE, tag!("}")) ~
+        annotations: annotations,
     || Exception {
         ident: id,
         fields: fields,
+        annotations: annotations,
+        doc: doc,
     }));
 
 named!(service <Service>, chain!(
-    tag!("service")  ~ blank ~
-        id: identifier ~ blank? ~
+    doc: doc_comment ~
+        complete!(tag!("service"))  ~ blank ~
+        id: expect!(IDENTIFIER, identifier) ~ blank? ~
         ext: chain!(tag!("extends") ~ blank ~
-                    exid: identifier, || exid)? ~
+                    exid: expect!(IDENTIFIER, identifier), || exid)? ~
+        blank? ~
+        expect!(OPEN_BRACE, tag!("{")) ~
+        functions: many0!(committed!(function)) ~
         blank? ~
-        tag!("{") ~ blank? ~
-        functions: many0!(chain!(f: function ~ blank?, || f)) ~
-        tag!("}") ,
+        expect!(FUNCTION_OR_CLOSE_BRACE, tag!("}")) ~
+        annotations: annotations,
     || Service{
         extends: ext,
         ident: id,
         methods: functions,
+        annotations: annotations,
+        doc: doc,
+        ancestors: Vec::new(),
     }));
 
 named!(field <StructField>, chain!(
-    idx: chain!(idx: field_id ~  blank?, || idx)? ~
+    doc: doc_comment ~
+        idx: chain!(idx: field_id ~  blank?, || idx)? ~
         req: chain!(req: field_req ~ blank, || req)? ~
-        ty: field_type ~ blank ~
-        id: identifier ~ blank? ~
-        value: chain!(tag!("=") ~ blank? ~
-                      v: const_value, || v)? ~
-        list_separator?
+        ty: field_type ~
+        ty_annotations: annotations ~ blank ~
+        id: expect!(IDENTIFIER, identifier) ~
+        value: chain!(blank? ~ tag!("=") ~ blank? ~
+                      v: expect!(VALUE, const_value), || v)? ~
+        annotations: annotations ~
+        chain!(blank? ~ list_separator, ||())?
         ,
     || StructField {
         seq: idx,
-       optional: req.unwrap_or(false),
+        required: req == Some(false),
+        optional: req == Some(true),
         ty: ty,
         ident: id,
 //        value: None,
         value: value,
+        annotations: ty_annotations.into_iter().chain(annotations).collect(),
+        doc: doc,
     }));
 
+// Optional wherever they go, and so taking the blanks before them too.
+named!(annotations < Vec<Annotation> >, map!(
+    opt!(complete!(chain!(
+        blank? ~
+            tag!("(") ~ blank? ~
+            annotations: many0!(chain!(
+                key: identifier ~ blank? ~
+                    value: chain!(tag!("=") ~ blank? ~ v: expect!(LITERAL, literal), || v)? ~ blank? ~
+                    list_separator? ~ blank?,
+                || Annotation {
+                    key: key,
+                    value: value.unwrap_or_else(|| "1".to_string()),
+                })) ~
+            expect!(ANNOTATION_OR_CLOSE_PAREN, tag!(")")),
+        || annotations))),
+    |annotations: Option<Vec<Annotation>>| annotations.unwrap_or_else(Vec::new)));
+
 named!(field_id <i64>, chain!(id: int_constant ~ blank? ~ tag!(":"), || id));
 
+// whether the field is `optional`, `false` meaning `required`
 named!(field_req <bool>, alt!(
     tag!("required") => {|_| false}|
     tag!("optional") => {|_| true}));
 
 named!(function <ServiceMethod>, chain!(
-    oneway: chain!(tag!("oneway") ~ blank?, ||())? ~
-        ty: function_type ~ blank ~
-        id: identifier ~ blank? ~
-        tag!("(") ~ blank? ~
-        args: many0!(chain!(f: field ~ blank?, || f)) ~
-        tag!(")")  ~
-        throws: chain!(blank? ~ th: throws, || th)? ~
+    doc: doc_comment ~
+        oneway: chain!(tag!("oneway") ~ blank?, ||())? ~
+        ty: function_type ~
+        ty_annotations: annotations ~ blank ~
+        id: expect!(IDENTIFIER, identifier) ~ blank? ~
+        expect!(OPEN_PAREN, tag!("(")) ~
+        args: many0!(committed!(field)) ~
+        blank? ~
+        expect!(ARGUMENT_OR_CLOSE_PAREN, tag!(")"))  ~
+        throws: chain!(blank? ~ th: committed!(throws), || th)? ~
+        annotations: annotations ~
         chain!(blank? ~ list_separator, ||())?
         ,
     || {
@@ -456,27 +898,30 @@ named!(function <ServiceMethod>, chain!(
             ty: ty,
             args: args,
             throws: throws,
+            annotations: ty_annotations.into_iter().chain(annotations).collect(),
+            doc: doc,
         }}));
 
 named!(function_type <Ty>, alt!(
     tag!("void") => {|_| Ty::Void} |
-    field_type));
+    committed!(field_type)));
 
 named!(throws < Vec<StructField> >, chain!(
     tag!("throws") ~ blank? ~
-        tag!("(") ~ blank? ~
-        fields: many0!(chain!(f: field ~ blank?, || f)) ~
-        tag!(")"),
+        expect!(OPEN_PAREN, tag!("(")) ~
+        fields: many0!(committed!(field)) ~
+        blank? ~
+        expect!(EXCEPTION_OR_CLOSE_PAREN, tag!(")")),
     || fields));
 
 named!(field_type <Ty>, alt!(
     base_type |
-    container_type |
+    committed!(container_type) |
     identifier => {|i| Ty::Ident(i)}));
 
 named!(definition_type <Ty>, alt!(
     base_type |
-    container_type));
+    committed!(container_type)));
 
 named!(base_type <Ty>, alt!(
     tag!("bool")   => {|_| Ty::Bool} |
@@ -489,38 +934,38 @@ named!(base_type <Ty>, alt!(
     tag!("string") => {|_| Ty::String} |
     tag!("binary") => {|_| Ty::Binary}));
 
-named!(container_type <Ty>, alt!(map_type | set_type | list_type));
+named!(container_type <Ty>, alt!(committed!(map_type) | committed!(set_type) | committed!(list_type)));
 
 named!(map_type <Ty>, chain!(
     tag!("map") ~ blank? ~
         tag!("<") ~ blank? ~
-        k: field_type ~ blank? ~
-        tag!(",") ~ blank? ~
-        v: field_type ~ blank? ~
-        tag!(">"),
+        k: expect!(TYPE, field_type) ~ blank? ~
+        expect!(COMMA, tag!(",")) ~ blank? ~
+        v: expect!(TYPE, field_type) ~ blank? ~
+        expect!(CLOSE_ANGLE, tag!(">")),
     || Ty::Map(Box::new(k), Box::new(v))));
 
 named!(set_type <Ty>, chain!(
     tag!("set") ~ blank? ~
         tag!("<")  ~ blank? ~
-        v: field_type ~ blank?
-    ~ tag!(">"),
+        v: expect!(TYPE, field_type) ~ blank?
+    ~ expect!(CLOSE_ANGLE, tag!(">")),
     || Ty::Set(Box::new(v))));
 
 named!(list_type <Ty>, chain!(
     tag!("list") ~ blank? ~
         tag!("<")  ~ blank? ~
-        v: field_type ~ blank?
-    ~ tag!(">"),
+        v: expect!(TYPE, field_type) ~ blank?
+    ~ expect!(CLOSE_ANGLE, tag!(">")),
     || Ty::List(Box::new(v))));
 
 named!(const_value <ConstValue>, alt!(
     double_constant => {ConstValue::Double} |
     int_constant    => {ConstValue::Int} |
     literal         => {ConstValue::String} |
-    identifier      => {|_| panic!("identifier not supported")} |
-    const_list |
-    const_map));
+    identifier      => {ConstValue::Ident} |
+    committed!(const_list) |
+    committed!(const_map)));
 
 named!(int_constant <i64>, chain!
        (sgn: sgn? ~ n: map_res!(digit, from_utf8),
@@ -555,26 +1000,26 @@ named!(sgn <&str>, map_res!(alt!(tag!("+") | tag!("-")), from_utf8));
 
 named!(const_list <ConstValue>, chain!(
     tag!("[") ~ blank? ~
-        vs: many0!(chain!(
+        vs: many0!(committed!(chain!(
             v: const_value ~ blank? ~
-                list_separator? ~ blank?, || v)) ~
-        tag!("]"),
+                list_separator? ~ blank?, || v))) ~
+        expect!(VALUE_OR_CLOSE_BRACKET, tag!("]")),
     || ConstValue::List(vs)));
 
 named!(const_map <ConstValue>, chain!(
     tag!("{") ~ blank? ~
-        vs: many0!(chain!(
+        vs: many0!(committed!(chain!(
             k: const_value ~ blank? ~
-                tag!(":") ~  blank? ~
-                v: const_value ~ blank? ~
-                list_separator? ~ blank?, || v)) ~
-        tag!("}"),
-    || ConstValue::Map));
+                expect!(COLON, tag!(":")) ~  blank? ~
+                v: expect!(VALUE, const_value) ~ blank? ~
+                list_separator? ~ blank?, || (k, v)))) ~
+        expect!(ENTRY_OR_CLOSE_BRACE, tag!("}")),
+    || ConstValue::Map(vs)));
 
 
 named!(literal <String>, map_res!(alt!(
-    chain!(tag!("\"") ~ s: is_not!("\"") ~ tag!("\""), || s) |
-    chain!(tag!("'")  ~ s: is_not!("'")  ~ tag!("'") , || s)),
+    chain!(tag!("\"") ~ s: take_until!("\"") ~ tag!("\""), || s) |
+    chain!(tag!("'")  ~ s: take_until!("'")  ~ tag!("'") , || s)),
                                   |v| from_utf8(v).map(|s| s.to_string())));
 
 fn cat_vec(vs: Vec<&[u8]>) -> Vec<u8> {
@@ -598,15 +1043,43 @@ named!(identifier <String>, chain!(
 named!(list_separator, alt!(tag!(",") | tag!(";")));
 
 
-named!(blank <()>, map!(many1!(alt!(comment | map!(multispace, |_| ()))), |_|()));
+named!(blank <()>, map!(many1!(complete!(alt!(map!(comment, |_| ()) | map!(multispace, |_| ())))), |_|()));
+
+// Blanks, and the text of the last doc comment among them, which documents what
+// follows.
+named!(doc_comment <Option<String> >, map!(
+    many0!(complete!(alt!(comment | map!(multispace, |_| None)))),
+    |docs: Vec<Option<String>>| docs.into_iter().filter_map(|doc| doc).last()));
 
-named!(comment <()>, alt!(
+// The text of a `/** */` doc comment, `None` for the others.
+named!(comment <Option<String> >, alt!(
     chain!(
         tag!("/*") ~
-            take_until_and_consume!("*/"),
-        ||()) |
-    chain!(tag!("//") ~ take_until_and_consume!("\n"), ||()) |
-    chain!(tag!("#") ~ take_until_and_consume!("\n"), ||())));
+            text: take_until_and_consume!("*/"),
+        || doc_text(text)) |
+    chain!(tag!("//") ~ take_until_and_consume!("\n"), || None) |
+    chain!(tag!("#") ~ take_until_and_consume!("\n"), || None)));
+
+/// Strips the `*` starting the lines of a doc comment, and the blank lines around.
+fn doc_text(text: &[u8]) -> Option<String> {
+    if !text.starts_with(b"*") {
+        return None;
+    }
+    let text = String::from_utf8_lossy(&text[1..]);
+    let lines: Vec<&str> = text.lines()
+        .map(|line| {
+            let line = line.trim();
+            if line.starts_with('*') { line[1..].trim() } else { line }
+        })
+        .collect();
+    let start = lines.iter().position(|line| !line.is_empty()).unwrap_or(lines.len());
+    let end = lines.iter().rposition(|line| !line.is_empty()).map_or(start, |i| i + 1);
+    if start == end {
+        None
+    } else {
+        Some(lines[start..end].join("\n"))
+    }
+}
 
 
 
@@ -637,7 +1110,7 @@ struct Foo {}
                Document{
                    headers: vec![Header::Include(Include {path: "foo.thrift".to_string()})],
                    definitions: vec![Definition::Const(Const {ident: "foo".to_string(), ty: Ty::I32, value: ConstValue::Int(1)}),
-                                     Definition::Struct(Struct {ident: "Foo".to_string(), fields: vec![],})]}
+                                     Definition::Struct(Struct {ident: "Foo".to_string(), fields: vec![], annotations: vec![], doc: None})]}
     );
 
 
@@ -685,36 +1158,47 @@ fn test_definition() {
     );
 
     assert_eq!(definition(b"typedef string foo").unwrap().1,
-               Definition::Typedef(Typedef {ty: Ty::String, ident: "foo".to_string()}));
+               Definition::Typedef(Typedef {ty: Ty::String, ident: "foo".to_string(), annotations: vec![]}));
 
     assert_eq!(definition(b"enum Foo {}").unwrap().1,
                Definition::Enum(Enum {
                    ident: "Foo".to_string(),
                    variants: vec![],
+                   annotations: vec![],
+                   doc: None,
                }));
 
     assert_eq!(definition(b"struct Foo {}").unwrap().1,
                Definition::Struct(Struct {
                    ident: "Foo".to_string(),
                    fields: vec![],
+                   annotations: vec![],
+                   doc: None,
                }));
 
     assert_eq!(definition(b"union Foo {}").unwrap().1,
                Definition::Union(Union {
                    ident: "Foo".to_string(),
                    fields: vec![],
+                   annotations: vec![],
+                   doc: None,
                }));
 
     assert_eq!(definition(b"exception Foo {}").unwrap().1,
                Definition::Exception(Exception {
                    ident: "Foo".to_string(),
                    fields: vec![],
+                   annotations: vec![],
+                   doc: None,
                }));
     assert_eq!(definition(b"service Foo {} ").unwrap().1,
                Definition::Service(Service {
                    extends: None,
                    ident: "Foo".to_string(),
                    methods: vec![],
+                   ancestors: vec![],
+                   annotations: vec![],
+                   doc: None,
                }));
 
 }
@@ -733,7 +1217,7 @@ fn test_const() {
 #[test]
 fn test_typedef() {
     assert_eq!(typedef(b"typedef string foo").unwrap().1,
-               Typedef {ty: Ty::String, ident: "foo".to_string()});
+               Typedef {ty: Ty::String, ident: "foo".to_string(), annotations: vec![]});
 }
 
 
@@ -743,6 +1227,8 @@ fn test_enum() {
                Enum {
                    ident: "Foo".to_string(),
                    variants: vec![],
+                   annotations: vec![],
+                   doc: None,
                });
 
     assert_eq!(enum_(b"enum Foo {
@@ -750,7 +1236,9 @@ foo
 }").unwrap().1,
                Enum {
                    ident: "Foo".to_string(),
-                   variants: vec![Variant{ident:"foo".to_string(), seq: None}]
+                   variants: vec![Variant{ident:"foo".to_string(), seq: None, annotations: vec![], doc: None}],
+                   annotations: vec![],
+                   doc: None,
                });
 
     assert_eq!(enum_(b"enum Foo {
@@ -758,7 +1246,9 @@ foo
 }").unwrap().1,
                Enum {
                    ident: "Foo".to_string(),
-                   variants: vec![Variant{ident: "foo".to_string(), seq: None}]
+                   variants: vec![Variant{ident: "foo".to_string(), seq: None, annotations: vec![], doc: None}],
+                   annotations: vec![],
+                   doc: None,
                });
 
     assert_eq!(enum_(b"enum Foo {
@@ -767,9 +1257,11 @@ bar
 }").unwrap().1,
                Enum {
                    ident: "Foo".to_string(),
-                   variants: vec![Variant {ident: "foo".to_string(), seq: None},
-                                  Variant{ident: "bar".to_string(), seq: None}
-                   ]
+                   variants: vec![Variant {ident: "foo".to_string(), seq: None, annotations: vec![], doc: None},
+                                  Variant{ident: "bar".to_string(), seq: None, annotations: vec![], doc: None}
+                   ],
+                   annotations: vec![],
+                   doc: None,
                });
 
     assert_eq!(enum_(b"enum Foo {
@@ -778,9 +1270,11 @@ bar,
 }").unwrap().1,
                Enum {
                    ident: "Foo".to_string(),
-                   variants: vec![Variant{ident: "foo".to_string(), seq: None},
-                                  Variant{ident: "bar".to_string(), seq: None}
-                   ]
+                   variants: vec![Variant{ident: "foo".to_string(), seq: None, annotations: vec![], doc: None},
+                                  Variant{ident: "bar".to_string(), seq: None, annotations: vec![], doc: None}
+                   ],
+                   annotations: vec![],
+                   doc: None,
                });
     assert_eq!(enum_(b"enum Foo {
 foo;
@@ -788,9 +1282,11 @@ bar;
 }").unwrap().1,
                Enum {
                    ident: "Foo".to_string(),
-                   variants: vec![Variant{ident: "foo".to_string(), seq: None},
-                                  Variant{ident: "bar".to_string(), seq: None}
-                   ]
+                   variants: vec![Variant{ident: "foo".to_string(), seq: None, annotations: vec![], doc: None},
+                                  Variant{ident: "bar".to_string(), seq: None, annotations: vec![], doc: None}
+                   ],
+                   annotations: vec![],
+                   doc: None,
                });
 }
 
@@ -801,6 +1297,8 @@ fn test_struct() {
                Struct {
                    ident: "Foo".to_string(),
                    fields: vec![],
+                   annotations: vec![],
+                   doc: None,
                });
 
     assert_eq!(struct_(b"struct Foo {1: required string foo}").unwrap().1,
@@ -810,10 +1308,15 @@ fn test_struct() {
                        StructField {
                            seq: Some(1),
                            optional: false,
+                           required: true,
                            ident: "foo".to_string(),
                            ty: Ty::String,
                            value: None,
+                           annotations: vec![],
+                           doc: None,
                        }],
+                   annotations: vec![],
+                   doc: None,
                });
 }
 
@@ -823,6 +1326,8 @@ fn test_union() {
                Union {
                    ident: "Foo".to_string(),
                    fields: vec![],
+                   annotations: vec![],
+                   doc: None,
                });
 
     assert_eq!(union(b"union Foo {1: required string foo}").unwrap().1,
@@ -832,10 +1337,15 @@ fn test_union() {
                        StructField {
                            seq: Some(1),
                            optional: false,
+                           required: true,
                            ident: "foo".to_string(),
                            ty: Ty::String,
                            value: None,
+                           annotations: vec![],
+                           doc: None,
                        }],
+                   annotations: vec![],
+                   doc: None,
                });
 }
 
@@ -846,6 +1356,8 @@ fn test_exception() {
                Exception{
                    ident: "Foo".to_string(),
                    fields: vec![],
+                   annotations: vec![],
+                   doc: None,
                });
 
     assert_eq!(exception(b"exception Foo {1: required string foo}").unwrap().1,
@@ -855,10 +1367,15 @@ fn test_exception() {
                        StructField {
                            seq: Some(1),
                            optional: false,
+                           required: true,
                            ident: "foo".to_string(),
                            ty: Ty::String,
                            value: None,
+                           annotations: vec![],
+                           doc: None,
                        }],
+                   annotations: vec![],
+                   doc: None,
                });
 }
 
@@ -870,6 +1387,9 @@ fn test_service() {
 extends: None,
 ident: "Foo".to_string(),
 methods: vec![],
+ancestors: vec![],
+annotations: vec![],
+doc: None,
 });
 
     assert_eq!(service(b"service Foo {
@@ -885,8 +1405,13 @@ methods: vec![
         ty: Ty::Void,
         args: vec![],
         throws: None,
+        annotations: vec![],
+        doc: None,
     }
 ],
+ancestors: vec![],
+annotations: vec![],
+doc: None,
 });
 
     assert_eq!(service(b"service Foo {
@@ -902,8 +1427,13 @@ methods: vec![
         ty: Ty::Void,
         args: vec![],
         throws: None,
+        annotations: vec![],
+        doc: None,
     }
 ],
+ancestors: vec![],
+annotations: vec![],
+doc: None,
 });
 
     assert_eq!(service(b"service Foo {
@@ -920,6 +1450,8 @@ methods: vec![
         ty: Ty::Void,
         args: vec![],
         throws: None,
+        annotations: vec![],
+        doc: None,
     },
     ServiceMethod {
         oneway: false,
@@ -927,8 +1459,13 @@ methods: vec![
         ty: Ty::Void,
         args: vec![],
         throws: None,
+        annotations: vec![],
+        doc: None,
     }
 ],
+ancestors: vec![],
+annotations: vec![],
+doc: None,
 });
 
     assert_eq!(service(b"service Foo {
@@ -945,6 +1482,8 @@ methods: vec![
         ty: Ty::Void,
         args: vec![],
         throws: None,
+        annotations: vec![],
+        doc: None,
     },
     ServiceMethod {
         oneway: false,
@@ -952,8 +1491,13 @@ methods: vec![
         ty: Ty::Void,
         args: vec![],
         throws: None,
+        annotations: vec![],
+        doc: None,
     }
 ],
+ancestors: vec![],
+annotations: vec![],
+doc: None,
 });
 
     assert_eq!(service(b"service Foo {
@@ -970,6 +1514,8 @@ methods: vec![
         ty: Ty::Void,
         args: vec![],
         throws: None,
+        annotations: vec![],
+        doc: None,
     },
     ServiceMethod {
         oneway: false,
@@ -977,8 +1523,13 @@ methods: vec![
         ty: Ty::Void,
         args: vec![],
         throws: None,
+        annotations: vec![],
+        doc: None,
     }
 ],
+ancestors: vec![],
+annotations: vec![],
+doc: None,
 });
 
 
@@ -995,8 +1546,13 @@ methods: vec![
         ty: Ty::Void,
         args: vec![],
         throws: None,
+        annotations: vec![],
+        doc: None,
     },
 ],
+ancestors: vec![],
+annotations: vec![],
+doc: None,
 });
 
 }
@@ -1006,43 +1562,61 @@ fn test_field() {
     assert_eq!(field(b"string foo;").unwrap().1,
                StructField {seq: None,
                             optional: false,
+                            required: false,
                             ident: "foo".to_string(),
                             ty: Ty::String,
                             value: None,
+                            annotations: vec![],
+                            doc: None,
                });
     assert_eq!(field(b"1: string foo;").unwrap().1,
                StructField {seq: Some(1),
                             optional: false,
+                            required: false,
                             ident: "foo".to_string(),
                             ty: Ty::String,
                             value: None,
+                            annotations: vec![],
+                            doc: None,
                });
     assert_eq!(field(b"1: i32 foo;").unwrap().1,
                StructField {seq: Some(1),
                             optional: false,
+                            required: false,
                             ident: "foo".to_string(),
                             ty: Ty::I32,
-                            value: None,});
+                            value: None,
+                            annotations: vec![],
+                            doc: None,});
     assert_eq!(field(b"1: i32 foo = 3;").unwrap().1,
                StructField {seq: Some(1),
                             optional: false,
+                            required: false,
                             ident: "foo".to_string(),
                             ty: Ty::I32,
                             value: Some(ConstValue::Int(3)),
+                            annotations: vec![],
+                            doc: None,
                });
     assert_eq!(field(b"2: required set<binary> foo,").unwrap().1,
                StructField {seq: Some(2),
                             optional: false,
+                            required: true,
                             ident: "foo".to_string(),
                             ty: Ty::Set(Box::new(Ty::Binary)),
                             value: None,
+                            annotations: vec![],
+                            doc: None,
                });
     assert_eq!(field(b"3: optional string foo;").unwrap().1,
                StructField {seq: Some(3),
                             optional: true,
+                            required: false,
                             ident: "foo".to_string(),
                             ty: Ty::String,
                             value: None,
+                            annotations: vec![],
+                            doc: None,
                });
 
 }
@@ -1068,6 +1642,8 @@ fn test_function() {
                    ty: Ty::I32,
                    args: vec![],
                    throws: None,
+                   annotations: vec![],
+                   doc: None,
                });
 
     assert_eq!(function(b"i32 foo(1: string bar);").unwrap().1,
@@ -1079,12 +1655,17 @@ fn test_function() {
                        StructField {
                            seq: Some(1),
                            optional: false,
+                           required: false,
                            ident: "bar".to_string(),
                            ty: Ty::String,
                            value: None,
+                           annotations: vec![],
+                           doc: None,
                        },
                    ],
                    throws: None,
+                   annotations: vec![],
+                   doc: None,
                });
     assert_eq!(function(b"i32 foo(1: required string bar);").unwrap().1,
                ServiceMethod {
@@ -1095,12 +1676,17 @@ fn test_function() {
                        StructField {
                            seq: Some(1),
                            optional: false,
+                           required: true,
                            ident: "bar".to_string(),
                            ty: Ty::String,
                            value: None,
+                           annotations: vec![],
+                           doc: None,
                        },
                    ],
                    throws: None,
+                   annotations: vec![],
+                   doc: None,
                });
     assert_eq!(function(b"void foo(1: required string bar),").unwrap().1,
                ServiceMethod {
@@ -1111,12 +1697,17 @@ fn test_function() {
                        StructField {
                            seq: Some(1),
                            optional: false,
+                           required: true,
                            ident: "bar".to_string(),
                            ty: Ty::String,
                            value: None,
+                           annotations: vec![],
+                           doc: None,
                        },
                    ],
                    throws: None,
+                   annotations: vec![],
+                   doc: None,
                });
     assert_eq!(function(b"oneway void foo(1: required string bar);").unwrap().1,
                ServiceMethod {
@@ -1127,12 +1718,17 @@ fn test_function() {
                        StructField {
                            seq: Some(1),
                            optional: false,
+                           required: true,
                            ident: "bar".to_string(),
                            ty: Ty::String,
                            value: None,
+                           annotations: vec![],
+                           doc: None,
                        },
                    ],
                    throws: None,
+                   annotations: vec![],
+                   doc: None,
                });
     assert_eq!(function(b"oneway i32 foo(1: required string bar);").unwrap().1,
                ServiceMethod {
@@ -1143,12 +1739,17 @@ fn test_function() {
                        StructField {
                            seq: Some(1),
                            optional: false,
+                           required: true,
                            ident: "bar".to_string(),
                            ty: Ty::String,
                            value: None,
+                           annotations: vec![],
+                           doc: None,
                        },
                    ],
                    throws: None,
+                   annotations: vec![],
+                   doc: None,
                });
     assert_eq!(function(b"i32 foo(1: required string bar; optional binary baz);").unwrap().1,
                ServiceMethod {
@@ -1159,19 +1760,27 @@ fn test_function() {
                        StructField {
                            seq: Some(1),
                            optional: false,
+                           required: true,
                            ident: "bar".to_string(),
                            ty: Ty::String,
                            value: None,
+                           annotations: vec![],
+                           doc: None,
                        },
                        StructField {
                            seq: None,
                            optional: true,
+                           required: false,
                            ident: "baz".to_string(),
                            ty: Ty::Binary,
                            value: None,
+                           annotations: vec![],
+                           doc: None,
                        },
                    ],
                    throws: None,
+                   annotations: vec![],
+                   doc: None,
                });
     assert_eq!(function(b"i32 foo(1: required string bar, 2: optional binary baz);").unwrap().1,
                ServiceMethod {
@@ -1182,19 +1791,27 @@ fn test_function() {
                        StructField {
                            seq: Some(1),
                            optional: false,
+                           required: true,
                            ident: "bar".to_string(),
                            ty: Ty::String,
                            value: None,
+                           annotations: vec![],
+                           doc: None,
                        },
                        StructField {
                            seq: Some(2),
                            optional: true,
+                           required: false,
                            ident: "baz".to_string(),
                            ty: Ty::Binary,
                            value: None,
+                           annotations: vec![],
+                           doc: None,
                        },
                    ],
                    throws: None,
+                   annotations: vec![],
+                   doc: None,
                });
     assert_eq!(function(b"i32 foo(1: required string bar) throws (1: list<i32> pee);").unwrap().1,
                ServiceMethod {
@@ -1205,20 +1822,28 @@ fn test_function() {
                        StructField {
                            seq: Some(1),
                            optional: false,
+                           required: true,
                            ident: "bar".to_string(),
                            ty: Ty::String,
                            value: None,
+                           annotations: vec![],
+                           doc: None,
                        },
                    ],
                    throws: Some(vec![
                        StructField {
                            seq: Some(1),
                            optional: false,
+                           required: false,
                            ident: "pee".to_string(),
                            ty: Ty::List(Box::new(Ty::I32)),
                            value: None,
+                           annotations: vec![],
+                           doc: None,
                        }
                    ]),
+                   annotations: vec![],
+                   doc: None,
                });
 
     assert_eq!(function(b"i32 foo(1: required string bar) throws (1: list<i32> pee, 2: optional set<byte> poo),").unwrap().1,
@@ -1230,28 +1855,39 @@ fn test_function() {
                        StructField {
                            seq: Some(1),
                            optional: false,
+                           required: true,
                            ident: "bar".to_string(),
                            ty: Ty::String,
                            value: None,
+                           annotations: vec![],
+                           doc: None,
                        },
                    ],
                    throws: Some(vec![
                        StructField {
                            seq: Some(1),
                            optional: false,
+                           required: false,
                            ident: "pee".to_string(),
                            ty: Ty::List(Box::new(Ty::I32)),
                            value: None,
+                           annotations: vec![],
+                           doc: None,
                        },
                        StructField {
                            seq: Some(2),
                            optional: true,
+                           required: false,
                            ident: "poo".to_string(),
                            ty: Ty::Set(Box::new(Ty::Byte)),
                            value: None,
+                           annotations: vec![],
+                           doc: None,
                        },
 
                    ]),
+                   annotations: vec![],
+                   doc: None,
                });
 }
 
@@ -1268,30 +1904,42 @@ fn test_throws() {
     assert_eq!(throws(b"throws(1: string foo)").unwrap().1,
                vec![StructField {seq: Some(1),
                                  optional: false,
+                                 required: false,
                                  ident: "foo".to_string(),
                                  ty: Ty::String,
                                  value: None,
+                                 annotations: vec![],
+                                 doc: None,
                }]);
     assert_eq!(throws(b"throws( 1: string foo )").unwrap().1,
                vec![StructField {seq: Some(1),
                                  optional: false,
+                                 required: false,
                                  ident: "foo".to_string(),
                                  ty: Ty::String,
                                  value: None,
+                                 annotations: vec![],
+                                 doc: None,
                }]
     );
     assert_eq!(throws(b"throws(1: string foo, 2: optional i32 bar)").unwrap().1,
                vec![StructField {seq: Some(1),
                                  optional: false,
+                                 required: false,
                                  ident: "foo".to_string(),
                                  ty: Ty::String,
                                  value: None,
+                                 annotations: vec![],
+                                 doc: None,
                },
                     StructField {seq: Some(2),
                                  optional: true,
+                                 required: false,
                                  ident: "bar".to_string(),
                                  ty: Ty::I32,
                                  value: None,
+                                 annotations: vec![],
+                                 doc: None,
                     }]
     );
 }
@@ -1371,6 +2019,10 @@ fn test_const_value() {
     assert_eq!(const_value(b"-1.01 ").unwrap().1, ConstValue::Double(-1.01));
     assert_eq!(const_value(b"'aaa'").unwrap().1, ConstValue::String("aaa".to_string()));
     assert_eq!(const_value(b"\"aaa\"").unwrap().1, ConstValue::String("aaa".to_string()));
+    assert_eq!(const_value(b"Color.RED ").unwrap().1, ConstValue::Ident("Color.RED".to_string()));
+    assert_eq!(const_value(b"{1: 'a', 2: 'b'}").unwrap().1,
+               ConstValue::Map(vec![(ConstValue::Int(1), ConstValue::String("a".to_string())),
+                                    (ConstValue::Int(2), ConstValue::String("b".to_string()))]));
 }
 
 #[test]
@@ -1410,14 +2062,90 @@ fn test_identifier() {
 #[test]
 fn test_comment() {
     assert_eq!(comment(b"# aaaaa
-").unwrap().1, ());
+").unwrap().1, None);
     assert_eq!(comment(b"// aaaaa
-").unwrap().1, ());
-    assert_eq!(comment(b"/*aaa*/").unwrap().1, ());
+").unwrap().1, None);
+    assert_eq!(comment(b"/*aaa*/").unwrap().1, None);
     assert_eq!(comment(b"/*
 aaa
-*/").unwrap().1, ());
+*/").unwrap().1, None);
     assert_eq!(comment(b"/*
 * aaa
-*/").unwrap().1, ());
+*/").unwrap().1, None);
+    assert_eq!(comment(b"/**/").unwrap().1, None);
+    assert_eq!(comment(b"/** aaa */").unwrap().1, Some("aaa".to_string()));
+    assert_eq!(comment(b"/**
+ * aaa
+ *
+ * bbb
+ */").unwrap().1, Some("aaa\n\nbbb".to_string()));
+    assert_eq!(doc_comment(b"/** aaa */ // bbb\n /** ccc */ struct").unwrap(), (&b"struct"[..], Some("ccc".to_string())));
+}
+
+#[test]
+fn test_rearrange() {
+    let mut doc = Document::parse("struct Foo {\n  string a;\n  2: string b;\n  string c;\n}\n").unwrap();
+    doc.rearrange().unwrap();
+    match doc.definitions[0] {
+        Definition::Struct(ref s) => {
+            let seqs: Vec<_> = s.fields.iter().map(|f| f.seq).collect();
+            assert_eq!(seqs, vec![Some(-1), Some(2), Some(-2)]);
+        }
+        ref def => panic!("unexpected definition {:?}", def),
+    }
+
+    for text in &["struct Foo {\n  1: string a;\n  1: string b;\n}\n",
+                  "struct Foo {\n  40000: string a;\n}\n",
+                  "struct Foo {\n  1: string a;\n  2: string a;\n}\n",
+                  "service Foo {\n  void bar(1: i32 a, 1: i32 b);\n}\n"] {
+        let mut doc = Document::parse(text).unwrap();
+        match doc.rearrange() {
+            Err(Error::Semantic(_)) => (),
+            ret => panic!("unexpected result {:?} for {}", ret, text),
+        }
+    }
+}
+
+#[test]
+fn test_annotations() {
+    assert_eq!(annotations(b" (a = \"1\", b.c = 'x'; d)").unwrap().1,
+               vec![Annotation { key: "a".to_string(), value: "1".to_string() },
+                    Annotation { key: "b.c".to_string(), value: "x".to_string() },
+                    Annotation { key: "d".to_string(), value: "1".to_string() }]);
+    assert_eq!(annotations(b" foo").unwrap().1, vec![]);
+    assert_eq!(annotations(b" ( python.immutable = \"\" )").unwrap().1,
+               vec![Annotation { key: "python.immutable".to_string(), value: "".to_string() }]);
+    assert!(Document::parse("struct Foo {\n} ( python.immutable = \"\" )\nconst string E = ''\n").is_ok());
+
+    let text = "struct Foo {\n  1: list<i32> (rust.type = \"VecDeque\") a (rust.name = \"b\");\n} (x = \"y\")\n";
+    let mut doc = Document::parse(text).unwrap();
+    doc.rearrange().unwrap();
+    match doc.definitions[0] {
+        Definition::Struct(ref s) => {
+            assert_eq!(s.annotations, vec![Annotation { key: "x".to_string(), value: "y".to_string() }]);
+            assert_eq!(s.fields[0].ident, "a");
+            assert_eq!(s.fields[0].rust_ident(), "b");
+            assert_eq!(s.fields[0].ty, Ty::Custom("VecDeque".to_string(), Box::new(Ty::List(Box::new(Ty::I32)))));
+            assert_eq!(s.fields[0].ty.to_string(), "VecDeque<i32>");
+        }
+        ref def => panic!("unexpected definition {:?}", def),
+    }
+}
+
+#[test]
+fn test_parse_error() {
+    let error = |text: &str| Document::parse(text).unwrap_err();
+    assert_eq!(error("struct Foo {\n  1: i32 bar\n  2: list<i32> baz = [1, 2 = 3];\n}\n"),
+               ParseError {
+                   line: 3,
+                   column: 28,
+                   text: "  2: list<i32> baz = [1, 2 = 3];".to_string(),
+                   message: "expected a constant value or `]`".to_string(),
+               });
+    assert_eq!(error("service Foo {\n\ti32 bar(1: i32 baz]\n}\n").to_string(),
+               "2:20: expected an argument or `)`\n\ti32 bar(1: i32 baz]\n\t                  ^");
+    assert_eq!(error("struct Foo {\n  1: i32\n}\n").message, "expected an identifier");
+    assert_eq!(error("struct Foo {\n  1: map<i32 bar\n}\n").message, "expected `,`");
+    assert_eq!(error("enum Foo {\n  A\n}\nstrukt Bar {\n}\n").message, "expected a definition");
+    assert_eq!(error("struct Foo {\n  1: i32 bar\n").message, "unexpected end of file");
 }
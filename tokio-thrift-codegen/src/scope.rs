@@ -0,0 +1,63 @@
+//! Looking up what the names used in a document stand for.
+use std::collections::HashMap;
+use parser::*;
+use include::absolutize_definition;
+use super::find_rust_namespace;
+
+/// The definitions a document can refer to: its own by name, and those of the other
+/// files `load` returned by their `super::module::Name` path.
+pub struct Scope {
+    local: Vec<Definition>,
+    // names used in these are prefixed with their module, like in the document
+    modules: HashMap<String, Vec<Definition>>,
+}
+
+impl Scope {
+    pub fn new(doc: &Document, docs: &[Document]) -> Scope {
+        let mut modules = HashMap::new();
+        for other in docs.iter() {
+            if let Some(ns) = find_rust_namespace(other) {
+                let mut definitions = other.definitions.clone();
+                for def in definitions.iter_mut() {
+                    absolutize_definition(def, &ns.module);
+                }
+                modules.insert(ns.module.clone(), definitions);
+            }
+        }
+        Scope {
+            local: doc.definitions.clone(),
+            modules: modules,
+        }
+    }
+
+    /// Finds the definition of `name`, a plain name or a `super::module::Name` path.
+    pub fn find(&self, name: &str) -> Option<&Definition> {
+        let (definitions, name) = if name.starts_with("super::") {
+            let mut parts = name["super::".len()..].splitn(2, "::");
+            let module = parts.next().unwrap_or("");
+            match (self.modules.get(module), parts.next()) {
+                (Some(definitions), Some(name)) => (definitions, name),
+                _ => return None,
+            }
+        } else {
+            (&self.local, name)
+        };
+        definitions.iter().find(|def| def.ident() == name)
+    }
+
+    /// Follows typedefs down to the type they stand for.
+    pub fn resolve<'a>(&'a self, mut ty: &'a Ty) -> &'a Ty {
+        // a bound, as recursive typedefs never end
+        for _ in 0..64 {
+            let next = match *ty {
+                Ty::Ident(ref name) => match self.find(name) {
+                    Some(&Definition::Typedef(ref t)) => &t.ty,
+                    _ => return ty,
+                },
+                _ => return ty,
+            };
+            ty = next;
+        }
+        ty
+    }
+}
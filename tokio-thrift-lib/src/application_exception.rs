@@ -0,0 +1,132 @@
+use std::{error, fmt};
+use protocol::{Error, ThriftType, Serialize, Serializer, ThriftSerializer};
+use protocol::{Deserialize, Deserializer, ThriftDeserializer};
+
+/// The standard kinds of `TApplicationException`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ApplicationExceptionKind {
+    Unknown = 0,
+    UnknownMethod = 1,
+    InvalidMessageType = 2,
+    WrongMethodName = 3,
+    BadSequenceId = 4,
+    MissingResult = 5,
+    InternalError = 6,
+    ProtocolError = 7,
+    InvalidTransform = 8,
+    InvalidProtocol = 9,
+    UnsupportedClientType = 10,
+}
+
+impl ApplicationExceptionKind {
+    /// Unknown values are read as `Unknown`, like Apache's libraries do.
+    pub fn from_i32(val: i32) -> Self {
+        use self::ApplicationExceptionKind::*;
+        match val {
+            1 => UnknownMethod,
+            2 => InvalidMessageType,
+            3 => WrongMethodName,
+            4 => BadSequenceId,
+            5 => MissingResult,
+            6 => InternalError,
+            7 => ProtocolError,
+            8 => InvalidTransform,
+            9 => InvalidProtocol,
+            10 => UnsupportedClientType,
+            _ => Unknown,
+        }
+    }
+}
+
+/// Error raised by the Thrift machinery rather than declared in the IDL.
+///
+/// Servers send it as an `Exception` message, for instance when a handler fails
+/// or a call names an unknown method, and generated clients return it as the error
+/// of the call.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ApplicationException {
+    pub kind: ApplicationExceptionKind,
+    pub message: String,
+}
+
+impl ApplicationException {
+    pub fn new<S: Into<String>>(kind: ApplicationExceptionKind, message: S) -> Self {
+        ApplicationException {
+            kind: kind,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ApplicationException {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}: {}", self.kind, self.message)
+    }
+}
+
+impl error::Error for ApplicationException {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+impl Serialize for ApplicationException {
+    fn serialize<S>(&self, s: &mut S) -> Result<(), Error>
+        where S: Serializer + ThriftSerializer
+    {
+        try!(s.write_struct_begin("TApplicationException"));
+        try!(s.write_field_begin("message", ThriftType::String, 1));
+        try!(s.serialize_str(&self.message));
+        try!(s.write_field_end());
+        try!(s.write_field_begin("type", ThriftType::I32, 2));
+        try!(s.serialize_i32(self.kind as i32));
+        try!(s.write_field_end());
+        try!(s.write_field_stop());
+        try!(s.write_struct_end());
+        Ok(())
+    }
+}
+
+impl Deserialize for ApplicationException {
+    fn deserialize<D>(de: &mut D) -> Result<Self, Error>
+        where D: Deserializer + ThriftDeserializer
+    {
+        let mut ret = ApplicationException::new(ApplicationExceptionKind::Unknown, "");
+        try!(de.read_struct_begin());
+        loop {
+            let field = try!(de.read_field_begin());
+            match (field.seq, field.ty) {
+                (_, ThriftType::Stop) => break,
+                (1, ThriftType::String) => ret.message = try!(de.deserialize_str()),
+                (2, ThriftType::I32) => ret.kind = ApplicationExceptionKind::from_i32(try!(de.deserialize_i32())),
+                (_, ty) => try!(de.skip(ty)),
+            }
+            try!(de.read_field_end());
+        }
+        try!(de.read_struct_end());
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use protocol::{BinaryProtocol, Serialize, Deserialize};
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let exc = ApplicationException::new(ApplicationExceptionKind::UnknownMethod, "no foo");
+        let mut buf = Vec::new();
+        exc.serialize(&mut BinaryProtocol::new(&mut buf)).unwrap();
+        assert_eq!(buf,
+                   [11, 0, 1, 0, 0, 0, 6, b'n', b'o', b' ', b'f', b'o', b'o', 8, 0, 2, 0, 0, 0, 1, 0]);
+        let de = ApplicationException::deserialize(&mut BinaryProtocol::new(Cursor::new(buf)));
+        assert_eq!(de.unwrap(), exc);
+    }
+
+    #[test]
+    fn unknown_kind() {
+        assert_eq!(ApplicationExceptionKind::from_i32(42), ApplicationExceptionKind::Unknown);
+    }
+}
@@ -1,4 +1,5 @@
 extern crate byteorder;
+extern crate rustc_serialize;
 extern crate tokio_core;
 extern crate tokio_proto;
 extern crate tokio_service;
@@ -9,7 +10,10 @@ extern crate log;
 
 pub mod protocol;
 mod result;
+mod application_exception;
 pub mod transport;
 pub mod tokio;
+pub mod multiplexed;
 
 pub use result::{ThriftResult, ThriftError};
+pub use application_exception::{ApplicationException, ApplicationExceptionKind};
@@ -0,0 +1,603 @@
+//! Several services sharing one connection, like Apache's `TMultiplexedProtocol`.
+//!
+//! Clients name calls `Service:method`. On the server, `MultiplexedServer` routes each
+//! call to the service registered under that prefix and hands it the message with the
+//! plain method name, so generated `*Server`s work unchanged. Replies carry the plain
+//! method name, as with Apache's processors.
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::Arc;
+use futures::{future, Future, BoxFuture};
+use tokio_service::Service;
+use protocol::{Error, BinaryProtocol, ThriftType, ThriftMessageType, Sequenced, Oneway};
+use protocol::{Serialize, Serializer, ThriftSerializer};
+use protocol::{Deserialize, Deserializer, ThriftDeserializer};
+use application_exception::{ApplicationException, ApplicationExceptionKind};
+
+/// Separates the service name from the method name.
+pub const SEPARATOR: &'static str = ":";
+
+/// A call or reply to be sent to the service named `service`.
+#[derive(Debug, Clone)]
+pub struct MultiplexedMessage<T> {
+    pub service: String,
+    pub message: T,
+}
+
+impl<T: Serialize> Serialize for MultiplexedMessage<T> {
+    fn serialize<S>(&self, s: &mut S) -> Result<(), Error>
+        where S: Serializer + ThriftSerializer
+    {
+        self.message.serialize(&mut MultiplexedProtocol::new(s, &self.service))
+    }
+}
+
+impl<T: Sequenced> Sequenced for MultiplexedMessage<T> {
+    fn seq(&self) -> i32 {
+        self.message.seq()
+    }
+
+    fn set_seq(&mut self, seq: i32) {
+        self.message.set_seq(seq)
+    }
+}
+
+impl<T: Oneway> Oneway for MultiplexedMessage<T> {
+    fn is_oneway(&self) -> bool {
+        self.message.is_oneway()
+    }
+}
+
+/// Serializer adding the service prefix to the names of outgoing calls.
+pub struct MultiplexedProtocol<'a, S: 'a> {
+    inner: &'a mut S,
+    service: &'a str,
+}
+
+impl<'a, S: 'a> MultiplexedProtocol<'a, S> {
+    pub fn new(inner: &'a mut S, service: &'a str) -> Self {
+        MultiplexedProtocol {
+            inner: inner,
+            service: service,
+        }
+    }
+}
+
+impl<'a, S: Serializer + 'a> Serializer for MultiplexedProtocol<'a, S> {
+    fn serialize_bool(&mut self, val: bool) -> Result<(), Error> {
+        self.inner.serialize_bool(val)
+    }
+
+    fn serialize_str(&mut self, val: &str) -> Result<(), Error> {
+        self.inner.serialize_str(val)
+    }
+
+    fn serialize_string(&mut self, val: String) -> Result<(), Error> {
+        self.inner.serialize_string(val)
+    }
+
+    fn serialize_usize(&mut self, val: usize) -> Result<(), Error> {
+        self.inner.serialize_usize(val)
+    }
+
+    fn serialize_isize(&mut self, val: isize) -> Result<(), Error> {
+        self.inner.serialize_isize(val)
+    }
+
+    fn serialize_u64(&mut self, val: u64) -> Result<(), Error> {
+        self.inner.serialize_u64(val)
+    }
+
+    fn serialize_i64(&mut self, val: i64) -> Result<(), Error> {
+        self.inner.serialize_i64(val)
+    }
+
+    fn serialize_i32(&mut self, val: i32) -> Result<(), Error> {
+        self.inner.serialize_i32(val)
+    }
+
+    fn serialize_u32(&mut self, val: u32) -> Result<(), Error> {
+        self.inner.serialize_u32(val)
+    }
+
+    fn serialize_i16(&mut self, val: i16) -> Result<(), Error> {
+        self.inner.serialize_i16(val)
+    }
+
+    fn serialize_u16(&mut self, val: u16) -> Result<(), Error> {
+        self.inner.serialize_u16(val)
+    }
+
+    fn serialize_u8(&mut self, val: u8) -> Result<(), Error> {
+        self.inner.serialize_u8(val)
+    }
+
+    fn serialize_i8(&mut self, val: i8) -> Result<(), Error> {
+        self.inner.serialize_i8(val)
+    }
+
+    fn serialize_f64(&mut self, val: f64) -> Result<(), Error> {
+        self.inner.serialize_f64(val)
+    }
+
+    fn serialize_bytes(&mut self, val: &[u8]) -> Result<(), Error> {
+        self.inner.serialize_bytes(val)
+    }
+}
+
+impl<'a, S: ThriftSerializer + 'a> ThriftSerializer for MultiplexedProtocol<'a, S> {
+    fn write_message_begin(&mut self, name: &str, message_type: ThriftMessageType, seq: i32) -> Result<(), Error> {
+        match message_type {
+            ThriftMessageType::Call | ThriftMessageType::Oneway => {
+                let name = format!("{}{}{}", self.service, SEPARATOR, name);
+                self.inner.write_message_begin(&name, message_type, seq)
+            }
+            _ => self.inner.write_message_begin(name, message_type, seq),
+        }
+    }
+
+    fn write_struct_begin(&mut self, name: &str) -> Result<(), Error> {
+        self.inner.write_struct_begin(name)
+    }
+
+    fn write_struct_end(&mut self) -> Result<(), Error> {
+        self.inner.write_struct_end()
+    }
+
+    fn write_field_begin(&mut self, name: &str, ty: ThriftType, id: i16) -> Result<(), Error> {
+        self.inner.write_field_begin(name, ty, id)
+    }
+
+    fn write_field_end(&mut self) -> Result<(), Error> {
+        self.inner.write_field_end()
+    }
+
+    fn write_field_stop(&mut self) -> Result<(), Error> {
+        self.inner.write_field_stop()
+    }
+
+    fn write_list_begin(&mut self, ty: ThriftType, size: usize) -> Result<(), Error> {
+        self.inner.write_list_begin(ty, size)
+    }
+
+    fn write_list_end(&mut self) -> Result<(), Error> {
+        self.inner.write_list_end()
+    }
+
+    fn write_set_begin(&mut self, ty: ThriftType, size: usize) -> Result<(), Error> {
+        self.inner.write_set_begin(ty, size)
+    }
+
+    fn write_set_end(&mut self) -> Result<(), Error> {
+        self.inner.write_set_end()
+    }
+
+    fn write_map_begin(&mut self, key_ty: ThriftType, value_ty: ThriftType, size: usize) -> Result<(), Error> {
+        self.inner.write_map_begin(key_ty, value_ty, size)
+    }
+
+    fn write_map_end(&mut self) -> Result<(), Error> {
+        self.inner.write_map_end()
+    }
+
+    fn write_message_end(&mut self) -> Result<(), Error> {
+        self.inner.write_message_end()
+    }
+}
+
+/// Client service prefixing every call with the service name.
+///
+/// Wrap a connection made with `ThriftProto<MultiplexedMessage<Args>, Return>` and
+/// hand it to the generated `*Client::new`.
+#[derive(Clone)]
+pub struct MultiplexedClient<S> {
+    service: String,
+    inner: S,
+}
+
+impl<S> MultiplexedClient<S> {
+    pub fn new(service: &str, inner: S) -> Self {
+        MultiplexedClient {
+            service: service.to_string(),
+            inner: inner,
+        }
+    }
+}
+
+impl<S, Req> Service for MultiplexedClient<S>
+    where S: Service<Request = MultiplexedMessage<Req>>
+{
+    type Request = Req;
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn call(&self, req: Req) -> S::Future {
+        self.inner.call(MultiplexedMessage {
+            service: self.service.clone(),
+            message: req,
+        })
+    }
+}
+
+/// A call received by `MultiplexedServer`.
+///
+/// `message` is the whole call, re-encoded in binary protocol without the prefix.
+#[derive(Debug, Clone)]
+pub struct MultiplexedCall {
+    pub service: String,
+    pub name: String,
+    pub seq: i32,
+    pub message: Vec<u8>,
+}
+
+impl Deserialize for MultiplexedCall {
+    fn deserialize<D>(de: &mut D) -> Result<Self, Error>
+        where D: Deserializer + ThriftDeserializer
+    {
+        let msg = try!(de.read_message_begin());
+        let (service, name) = match msg.name.find(SEPARATOR) {
+            Some(i) => (&msg.name[..i], &msg.name[i + SEPARATOR.len()..]),
+            None => {
+                return Err(Error::from(io::Error::new(io::ErrorKind::InvalidData,
+                                                      format!("no service name in call to {}", msg.name))))
+            }
+        };
+        let mut message = Vec::new();
+        {
+            let mut out = BinaryProtocol::new(&mut message);
+            try!(out.write_message_begin(name, msg.ty, msg.seq));
+            let depth = de.limits().max_depth;
+            try!(copy_value(de, &mut out, ThriftType::Struct, depth));
+            try!(out.write_message_end());
+        }
+        try!(de.read_message_end());
+        Ok(MultiplexedCall {
+            service: service.to_string(),
+            name: name.to_string(),
+            seq: msg.seq,
+            message: message,
+        })
+    }
+}
+
+impl Sequenced for MultiplexedCall {
+    fn seq(&self) -> i32 {
+        self.seq
+    }
+
+    fn set_seq(&mut self, seq: i32) {
+        self.seq = seq;
+    }
+}
+
+/// A reply sent by `MultiplexedServer`, encoded in binary protocol.
+///
+/// `message` is empty for oneway calls, and nothing is sent then.
+#[derive(Debug, Clone)]
+pub struct MultiplexedReply {
+    pub seq: i32,
+    pub message: Vec<u8>,
+}
+
+impl Serialize for MultiplexedReply {
+    fn serialize<S>(&self, s: &mut S) -> Result<(), Error>
+        where S: Serializer + ThriftSerializer
+    {
+        if self.is_oneway() {
+            return Ok(());
+        }
+        let mut de = BinaryProtocol::new(io::Cursor::new(&self.message[..]));
+        let msg = try!(de.read_message_begin());
+        try!(s.write_message_begin(&msg.name, msg.ty, self.seq));
+        let depth = de.limits().max_depth;
+        try!(copy_value(&mut de, s, ThriftType::Struct, depth));
+        try!(s.write_message_end());
+        Ok(())
+    }
+}
+
+impl Sequenced for MultiplexedReply {
+    fn seq(&self) -> i32 {
+        self.seq
+    }
+
+    fn set_seq(&mut self, seq: i32) {
+        self.seq = seq;
+    }
+}
+
+impl Oneway for MultiplexedReply {
+    fn is_oneway(&self) -> bool {
+        self.message.is_empty()
+    }
+}
+
+impl MultiplexedReply {
+    /// Answers `call` with an `Exception` message.
+    pub fn exception(call: &MultiplexedCall, e: &ApplicationException) -> Result<Self, Error> {
+        let mut message = Vec::new();
+        {
+            let mut out = BinaryProtocol::new(&mut message);
+            try!(out.write_message_begin(&call.name, ThriftMessageType::Exception, call.seq));
+            try!(e.serialize(&mut out));
+            try!(out.write_message_end());
+        }
+        Ok(MultiplexedReply {
+            seq: call.seq,
+            message: message,
+        })
+    }
+}
+
+type Handler = Fn(MultiplexedCall) -> BoxFuture<MultiplexedReply, io::Error> + Send + Sync;
+
+/// Server routing calls to the generated `*Server`s by service name.
+///
+/// Serve it with `new_tcp_server::<MultiplexedCall, MultiplexedReply>`.
+#[derive(Clone)]
+pub struct MultiplexedServer {
+    services: HashMap<String, Arc<Handler>>,
+}
+
+impl MultiplexedServer {
+    pub fn new() -> Self {
+        MultiplexedServer { services: HashMap::new() }
+    }
+
+    /// Routes calls to `service:*` to `inner`, replacing any service already registered.
+    pub fn register<S>(&mut self, service: &str, inner: S)
+        where S: Service<Error = io::Error> + Send + Sync + 'static,
+              S::Request: Deserialize,
+              S::Response: Serialize,
+              S::Future: Send + 'static
+    {
+        let handler = move |call: MultiplexedCall| -> BoxFuture<MultiplexedReply, io::Error> {
+            let mut de = BinaryProtocol::new(io::Cursor::new(&call.message[..]));
+            let req = match S::Request::deserialize(&mut de) {
+                Ok(req) => req,
+                Err(e) => return future::err(io::Error::from(e)).boxed(),
+            };
+            let seq = call.seq;
+            inner.call(req)
+                .and_then(move |res| {
+                    let mut message = Vec::new();
+                    try!(res.serialize(&mut BinaryProtocol::new(&mut message)));
+                    Ok(MultiplexedReply {
+                        seq: seq,
+                        message: message,
+                    })
+                })
+                .boxed()
+        };
+        self.services.insert(service.to_string(), Arc::new(handler));
+    }
+}
+
+impl Service for MultiplexedServer {
+    type Request = MultiplexedCall;
+    type Response = MultiplexedReply;
+    type Error = io::Error;
+    type Future = BoxFuture<MultiplexedReply, io::Error>;
+
+    fn call(&self, req: MultiplexedCall) -> Self::Future {
+        match self.services.get(&req.service) {
+            Some(handler) => handler(req),
+            None => {
+                let e = ApplicationException::new(ApplicationExceptionKind::UnknownMethod,
+                                                  format!("unknown service {}", req.service));
+                future::result(MultiplexedReply::exception(&req, &e).map_err(io::Error::from)).boxed()
+            }
+        }
+    }
+}
+
+/// Reads one value of type `ty` from `de` and writes it to `s`, entering at most
+/// `depth` nested structs and containers, like `skip` does.
+fn copy_value<D, S>(de: &mut D, s: &mut S, ty: ThriftType, depth: usize) -> Result<(), Error>
+    where D: Deserializer + ThriftDeserializer,
+          S: Serializer + ThriftSerializer
+{
+    match ty {
+        ThriftType::Struct | ThriftType::Map | ThriftType::Set | ThriftType::List if depth == 0 => {
+            return Err(Error::DepthLimit);
+        }
+        _ => (),
+    }
+    match ty {
+        ThriftType::Bool => try!(s.serialize_bool(try!(de.deserialize_bool()))),
+        ThriftType::Byte => try!(s.serialize_i8(try!(de.deserialize_i8()))),
+        ThriftType::I16 => try!(s.serialize_i16(try!(de.deserialize_i16()))),
+        ThriftType::I32 => try!(s.serialize_i32(try!(de.deserialize_i32()))),
+        ThriftType::U64 | ThriftType::I64 => try!(s.serialize_i64(try!(de.deserialize_i64()))),
+        ThriftType::Double => try!(s.serialize_f64(try!(de.deserialize_f64()))),
+        ThriftType::String => try!(s.serialize_bytes(&try!(de.deserialize_bytes()))),
+        ThriftType::Struct => {
+            let name = try!(de.read_struct_begin());
+            try!(s.write_struct_begin(&name));
+            loop {
+                let field = try!(de.read_field_begin());
+                if field.ty == ThriftType::Stop {
+                    break;
+                }
+                try!(s.write_field_begin(field.name.as_ref().map_or("", |n| &n[..]), field.ty, field.seq));
+                try!(copy_value(de, s, field.ty, depth - 1));
+                try!(s.write_field_end());
+                try!(de.read_field_end());
+            }
+            try!(s.write_field_stop());
+            try!(s.write_struct_end());
+            try!(de.read_struct_end());
+        }
+        ThriftType::Map => {
+            let map = try!(de.read_map_begin());
+            try!(s.write_map_begin(map.key_ty, map.value_ty, map.size));
+            for _ in 0..map.size {
+                try!(copy_value(de, s, map.key_ty, depth - 1));
+                try!(copy_value(de, s, map.value_ty, depth - 1));
+            }
+            try!(s.write_map_end());
+            try!(de.read_map_end());
+        }
+        ThriftType::Set => {
+            let set = try!(de.read_set_begin());
+            try!(s.write_set_begin(set.ty, set.size));
+            for _ in 0..set.size {
+                try!(copy_value(de, s, set.ty, depth - 1));
+            }
+            try!(s.write_set_end());
+            try!(de.read_set_end());
+        }
+        ThriftType::List => {
+            let list = try!(de.read_list_begin());
+            try!(s.write_list_begin(list.ty, list.size));
+            for _ in 0..list.size {
+                try!(copy_value(de, s, list.ty, depth - 1));
+            }
+            try!(s.write_list_end());
+            try!(de.read_list_end());
+        }
+        ThriftType::Stop | ThriftType::Void => return Err(Error::BadType(ty as i8)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use futures::Future;
+    use futures::future::{self, BoxFuture};
+    use tokio_service::Service;
+    use protocol::{Error, BinaryProtocol, Limits, ThriftType, ThriftMessageType};
+    use protocol::{Serialize, Serializer, ThriftSerializer};
+    use protocol::{Deserialize, Deserializer, ThriftDeserializer};
+    use application_exception::{ApplicationException, ApplicationExceptionKind};
+    use std::io;
+    use super::*;
+
+    // a call carrying one i32 field, answered with the same value plus one
+    #[derive(Debug, PartialEq)]
+    struct Incr(i32);
+
+    impl Serialize for Incr {
+        fn serialize<S>(&self, s: &mut S) -> Result<(), Error>
+            where S: Serializer + ThriftSerializer
+        {
+            try!(s.write_message_begin("incr", ThriftMessageType::Call, 9));
+            try!(s.write_struct_begin("incr_args"));
+            try!(s.write_field_begin("n", ThriftType::I32, 1));
+            try!(self.0.serialize(s));
+            try!(s.write_field_end());
+            try!(s.write_field_stop());
+            try!(s.write_struct_end());
+            s.write_message_end()
+        }
+    }
+
+    impl Deserialize for Incr {
+        fn deserialize<D>(de: &mut D) -> Result<Self, Error>
+            where D: Deserializer + ThriftDeserializer
+        {
+            let msg = try!(de.read_message_begin());
+            assert_eq!(msg.name, "incr");
+            try!(de.read_struct_begin());
+            try!(de.read_field_begin());
+            let n = try!(de.deserialize_i32());
+            try!(de.read_field_end());
+            try!(de.read_field_begin());
+            try!(de.read_struct_end());
+            try!(de.read_message_end());
+            Ok(Incr(n))
+        }
+    }
+
+    struct Incrementer;
+
+    impl Service for Incrementer {
+        type Request = Incr;
+        type Response = Incr;
+        type Error = io::Error;
+        type Future = BoxFuture<Incr, io::Error>;
+
+        fn call(&self, req: Incr) -> Self::Future {
+            future::ok(Incr(req.0 + 1)).boxed()
+        }
+    }
+
+    fn call(server: &MultiplexedServer, service: &str, n: i32) -> io::Result<Vec<u8>> {
+        let msg = MultiplexedMessage {
+            service: service.to_string(),
+            message: Incr(n),
+        };
+        let mut buf = Vec::new();
+        msg.serialize(&mut BinaryProtocol::new(&mut buf)).unwrap();
+        let call = MultiplexedCall::deserialize(&mut BinaryProtocol::new(Cursor::new(buf))).unwrap();
+        let reply = try!(server.call(call).wait());
+        let mut buf = Vec::new();
+        reply.serialize(&mut BinaryProtocol::new(&mut buf)).unwrap();
+        Ok(buf)
+    }
+
+    #[test]
+    fn prefix_call_name() {
+        let msg = MultiplexedMessage {
+            service: "Calc".to_string(),
+            message: Incr(1),
+        };
+        let mut buf = Vec::new();
+        msg.serialize(&mut BinaryProtocol::new(&mut buf)).unwrap();
+        let msg = BinaryProtocol::new(Cursor::new(buf)).read_message_begin().unwrap();
+        assert_eq!(msg.name, "Calc:incr");
+    }
+
+    #[test]
+    fn route_by_service() {
+        let mut server = MultiplexedServer::new();
+        server.register("Calc", Incrementer);
+
+        let reply = call(&server, "Calc", 41).unwrap();
+        assert_eq!(Incr::deserialize(&mut BinaryProtocol::new(Cursor::new(reply))).unwrap(), Incr(42));
+
+        let reply = call(&server, "Other", 1).unwrap();
+        let mut de = BinaryProtocol::new(Cursor::new(reply));
+        let msg = de.read_message_begin().unwrap();
+        assert_eq!((&msg.name[..], msg.ty, msg.seq), ("incr", ThriftMessageType::Exception, 9));
+        let e = ApplicationException::deserialize(&mut de).unwrap();
+        assert_eq!(e.kind, ApplicationExceptionKind::UnknownMethod);
+    }
+
+    #[test]
+    fn call_follows_depth_limit() {
+        // a call whose only argument is a list of lists
+        let mut buf = Vec::new();
+        {
+            let mut s = BinaryProtocol::new(&mut buf);
+            s.write_message_begin("Calc:nest", ThriftMessageType::Call, 1).unwrap();
+            s.write_struct_begin("nest_args").unwrap();
+            s.write_field_begin("lists", ThriftType::List, 1).unwrap();
+            s.write_list_begin(ThriftType::List, 1).unwrap();
+            vec![1i32].serialize(&mut s).unwrap();
+            s.write_list_end().unwrap();
+            s.write_field_end().unwrap();
+            s.write_field_stop().unwrap();
+            s.write_struct_end().unwrap();
+            s.write_message_end().unwrap();
+        }
+
+        let limits = Limits { max_depth: 3, ..Limits::default() };
+        assert!(MultiplexedCall::deserialize(&mut BinaryProtocol::with_limits(Cursor::new(buf.clone()), limits)).is_ok());
+        let limits = Limits { max_depth: 2, ..Limits::default() };
+        match MultiplexedCall::deserialize(&mut BinaryProtocol::with_limits(Cursor::new(buf), limits)) {
+            Err(Error::DepthLimit) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn call_without_service_is_error() {
+        let mut buf = Vec::new();
+        Incr(1).serialize(&mut BinaryProtocol::new(&mut buf)).unwrap();
+        assert!(MultiplexedCall::deserialize(&mut BinaryProtocol::new(Cursor::new(buf))).is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use super::{Serializer, Deserializer, ThriftSerializer, ThriftField, ThriftMessage, ThriftDeserializer, ThriftMessageType, ThriftType, Error};
+use super::{Limits, Serializer, Deserializer, ThriftSerializer, ThriftField, ThriftList, ThriftSet, ThriftMap, ThriftMessage, ThriftDeserializer, ThriftMessageType, ThriftType, Error};
 use transport::{VoidTransport, ReadTransport, WriteTransport};
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use std::iter;
@@ -11,7 +11,9 @@ pub const THRIFT_VERSION_MASK: i32 = 0xffff0000;
 pub const THRIFT_TYPE_MASK: i32 = 0x000000ff;
 
 pub struct BinaryProtocol<T>{
-    inner: T
+    inner: T,
+    limits: Limits,
+    depth: usize,
 }
 
 impl <T>BinaryProtocol<T> {
@@ -22,8 +24,14 @@ impl <T>BinaryProtocol<T> {
 
 impl <T: VoidTransport>BinaryProtocol<T> {
     pub fn new(inner: T) -> Self {
+        Self::with_limits(inner, Limits::default())
+    }
+
+    pub fn with_limits(inner: T, limits: Limits) -> Self {
         BinaryProtocol{
             inner: inner,
+            limits: limits,
+            depth: 0,
         }
     }
 }
@@ -95,7 +103,7 @@ impl <T: WriteTransport> Serializer for BinaryProtocol<T> {
 
     fn serialize_bytes(&mut self, val: &[u8]) -> Result<(), Error> {
         try!(self.serialize_i32(val.len() as i32));
-        try!(self.inner.write(val));
+        try!(self.inner.write_all(val));
         Ok(())
     }
 
@@ -109,12 +117,12 @@ impl <T: WriteTransport> Serializer for BinaryProtocol<T> {
 }
 
 impl <T: WriteTransport>ThriftSerializer for BinaryProtocol<T> {
-    fn write_message_begin(&mut self, name: &str, message_type: ThriftMessageType) -> Result<(), Error> {
+    fn write_message_begin(&mut self, name: &str, message_type: ThriftMessageType, seq: i32) -> Result<(), Error> {
         let version = THRIFT_VERSION_1 | message_type as i32;
 
         try!(self.serialize_i32(version));
         try!(self.serialize_str(name));
-        try!(self.serialize_i16(0));
+        try!(self.serialize_i32(seq));
 
         Ok(())
     }
@@ -142,12 +150,41 @@ impl <T: WriteTransport>ThriftSerializer for BinaryProtocol<T> {
         Ok(())
     }
 
+    fn write_list_begin(&mut self, ty: ThriftType, size: usize) -> Result<(), Error> {
+        try!(self.serialize_i8(ty as i8));
+        try!(self.serialize_i32(size as i32));
+        Ok(())
+    }
+
+    fn write_set_begin(&mut self, ty: ThriftType, size: usize) -> Result<(), Error> {
+        try!(self.serialize_i8(ty as i8));
+        try!(self.serialize_i32(size as i32));
+        Ok(())
+    }
+
+    fn write_map_begin(&mut self, key_ty: ThriftType, value_ty: ThriftType, size: usize) -> Result<(), Error> {
+        try!(self.serialize_i8(key_ty as i8));
+        try!(self.serialize_i8(value_ty as i8));
+        try!(self.serialize_i32(size as i32));
+        Ok(())
+    }
+
     fn write_message_end(&mut self) -> Result<(), Error> {
         Ok(())
     }
 }
 
 
+impl <T: ReadTransport>BinaryProtocol<T> {
+    fn read_container_size(&mut self) -> Result<usize, Error> {
+        let size = try!(self.deserialize_i32());
+        if size < 0 {
+            return Err(Error::NegativeSize(size));
+        }
+        try!(self.limits.check_container_size(size as usize));
+        Ok(size as usize)
+    }
+}
 
 impl<T: ReadTransport> Deserializer for BinaryProtocol<T> {
     fn deserialize_bool(&mut self) -> Result<bool, Error> {
@@ -200,11 +237,15 @@ impl<T: ReadTransport> Deserializer for BinaryProtocol<T> {
 
 
     fn deserialize_bytes(&mut self) -> Result<Vec<u8>, Error> {
-        let len = try!(self.deserialize_i32()) as usize;
-        let mut buf = Vec::with_capacity(len);
+        let len = try!(self.deserialize_i32());
+        if len < 0 {
+            return Err(Error::NegativeSize(len));
+        }
+        try!(self.limits.check_string_size(len as usize));
+        let mut buf = Vec::with_capacity(len as usize);
 
-        buf.extend(iter::repeat(0).take(len));
-        try!(self.inner.read(&mut buf));
+        buf.extend(iter::repeat(0).take(len as usize));
+        try!(self.inner.read_exact(&mut buf));
 
         Ok(buf)
     }
@@ -217,6 +258,10 @@ impl<T: ReadTransport> Deserializer for BinaryProtocol<T> {
 }
 
 impl<T: ReadTransport> ThriftDeserializer for BinaryProtocol<T> {
+    fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
     fn read_message_begin(&mut self) -> Result<ThriftMessage, Error> {
         let size: i32 = try!(self.deserialize_i32());
 
@@ -227,8 +272,8 @@ impl<T: ReadTransport> ThriftDeserializer for BinaryProtocol<T> {
             } else {
                 Ok(ThriftMessage {
                     name: try!(self.deserialize_str()),
-                    ty: ThriftMessageType::from((size & THRIFT_TYPE_MASK) as i8),
-                    seq: try!(self.deserialize_i16())
+                    ty: try!(ThriftMessageType::from_i8((size & THRIFT_TYPE_MASK) as i8)),
+                    seq: try!(self.deserialize_i32())
                 })
             }
         } else {
@@ -241,17 +286,20 @@ impl<T: ReadTransport> ThriftDeserializer for BinaryProtocol<T> {
     }
 
     fn read_struct_begin(&mut self) -> Result<String, Error> {
+        self.depth += 1;
+        try!(self.limits.check_depth(self.depth));
         Ok("".to_string())
     }
 
     fn read_struct_end(&mut self) -> Result<(), Error> {
+        self.depth = self.depth.saturating_sub(1);
         Ok(())
     }
 
     fn read_field_begin(&mut self) -> Result<ThriftField, Error> {
         let mut field = ThriftField {
             name: None,
-            ty: ThriftType::from(try!(self.deserialize_i8())),
+            ty: try!(ThriftType::from_i8(try!(self.deserialize_i8()))),
             seq: 0
         };
 
@@ -266,14 +314,53 @@ impl<T: ReadTransport> ThriftDeserializer for BinaryProtocol<T> {
     fn read_field_end(&mut self) -> Result<(), Error> {
         Ok(())
     }
+
+    fn read_list_begin(&mut self) -> Result<ThriftList, Error> {
+        let ty = try!(ThriftType::from_i8(try!(self.deserialize_i8())));
+        Ok(ThriftList {
+            ty: ty,
+            size: try!(self.read_container_size()),
+        })
+    }
+
+    fn read_list_end(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn read_set_begin(&mut self) -> Result<ThriftSet, Error> {
+        let ty = try!(ThriftType::from_i8(try!(self.deserialize_i8())));
+        Ok(ThriftSet {
+            ty: ty,
+            size: try!(self.read_container_size()),
+        })
+    }
+
+    fn read_set_end(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn read_map_begin(&mut self) -> Result<ThriftMap, Error> {
+        let key_ty = try!(ThriftType::from_i8(try!(self.deserialize_i8())));
+        let value_ty = try!(ThriftType::from_i8(try!(self.deserialize_i8())));
+        Ok(ThriftMap {
+            key_ty: key_ty,
+            value_ty: value_ty,
+            size: try!(self.read_container_size()),
+        })
+    }
+
+    fn read_map_end(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
 }
 
 
 #[cfg(test)]
 mod tests {
     use std::io::{Cursor, Write};
+    use std::collections::{BTreeMap, HashMap, HashSet};
     use byteorder::{ReadBytesExt, WriteBytesExt, BigEndian};
-    use protocol::{ThriftMessageType, ThriftDeserializer, ThriftSerializer, Serializer, Deserialize};
+    use protocol::{ThriftMessageType, ThriftType, ThriftDeserializer, ThriftSerializer, Serializer, Serialize, Deserialize};
     use super::*;
 
 
@@ -358,6 +445,158 @@ mod tests {
         assert_eq!(&*val, "foobar");
     }
 
+    #[test]
+    fn deserialize_truncated_string() {
+        let mut de = BinaryProtocol::new(Cursor::new(vec![0, 0, 0, 6, b'f', b'o']));
+        match de.deserialize_str() {
+            Err(Error::EOF) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn deserialize_negative_length() {
+        let mut de = BinaryProtocol::new(Cursor::new(vec![0xff, 0xff, 0xff, 0xfe]));
+        match de.deserialize_bytes() {
+            Err(Error::NegativeSize(-2)) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn deserialize_string_over_limit() {
+        let limits = Limits { max_string_size: 4, ..Limits::default() };
+        let mut de = BinaryProtocol::with_limits(Cursor::new(vec![0x7f, 0xff, 0xff, 0xff]), limits);
+        match de.deserialize_bytes() {
+            Err(Error::SizeLimit(0x7fffffff)) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn read_struct_begin_over_depth() {
+        let limits = Limits { max_depth: 2, ..Limits::default() };
+        let mut de = BinaryProtocol::with_limits(Cursor::new(vec![]), limits);
+        assert!(de.read_struct_begin().is_ok());
+        assert!(de.read_struct_begin().is_ok());
+        assert!(de.read_struct_end().is_ok());
+        assert!(de.read_struct_begin().is_ok());
+        match de.read_struct_begin() {
+            Err(Error::DepthLimit) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn serialize_list() {
+        let mut v = Vec::new();
+        assert!(vec![1i16, 2].serialize(&mut BinaryProtocol::new(&mut v)).is_ok());
+        assert_eq!(v, [6, 0, 0, 0, 2, 0, 1, 0, 2]);
+    }
+
+    #[test]
+    fn containers_round_trip() {
+        let mut map = HashMap::new();
+        map.insert("a".to_string(), vec![1i32, 2, 3]);
+        map.insert("b".to_string(), vec![]);
+        let mut set = HashSet::new();
+        set.insert(-1i64);
+        let mut tree = BTreeMap::new();
+        tree.insert(7i8, true);
+
+        let mut v = Vec::new();
+        {
+            let mut s = BinaryProtocol::new(&mut v);
+            assert!(map.serialize(&mut s).is_ok());
+            assert!(set.serialize(&mut s).is_ok());
+            assert!(tree.serialize(&mut s).is_ok());
+            assert!(Some(3.5f64).serialize(&mut s).is_ok());
+        }
+
+        let mut de = BinaryProtocol::new(Cursor::new(v));
+        let map2: HashMap<String, Vec<i32>> = Deserialize::deserialize(&mut de).unwrap();
+        let set2: HashSet<i64> = Deserialize::deserialize(&mut de).unwrap();
+        let tree2: BTreeMap<i8, bool> = Deserialize::deserialize(&mut de).unwrap();
+        let opt: Option<f64> = Deserialize::deserialize(&mut de).unwrap();
+        assert_eq!(map2, map);
+        assert_eq!(set2, set);
+        assert_eq!(tree2, tree);
+        assert_eq!(opt, Some(3.5));
+    }
+
+    #[test]
+    fn skip_nested() {
+        let mut map = HashMap::new();
+        map.insert("a".to_string(), vec![vec![1i32], vec![2, 3]]);
+
+        let mut v = Vec::new();
+        {
+            let mut s = BinaryProtocol::new(&mut v);
+            assert!(s.write_struct_begin("Foo").is_ok());
+            assert!(s.write_field_begin("map", ThriftType::Map, 1).is_ok());
+            assert!(map.serialize(&mut s).is_ok());
+            assert!(s.write_field_end().is_ok());
+            assert!(s.write_field_begin("name", ThriftType::String, 2).is_ok());
+            assert!(s.serialize_str("foo").is_ok());
+            assert!(s.write_field_end().is_ok());
+            assert!(s.write_field_stop().is_ok());
+            assert!(s.write_struct_end().is_ok());
+            assert!(s.serialize_i16(42).is_ok());
+        }
+
+        let mut de = BinaryProtocol::new(Cursor::new(v));
+        assert!(de.skip(ThriftType::Struct).is_ok());
+        assert_eq!(de.deserialize_i16().unwrap(), 42);
+    }
+
+    #[test]
+    fn skip_too_deep() {
+        let mut v = Vec::new();
+        for _ in 0..100 {
+            v.extend_from_slice(&[15, 0, 0, 0, 1]);
+        }
+        let mut de = BinaryProtocol::new(Cursor::new(v));
+        match de.skip(ThriftType::List) {
+            Err(Error::DepthLimit) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn skip_follows_depth_limit() {
+        // a list of lists of one i32
+        let v = vec![15, 0, 0, 0, 1, 8, 0, 0, 0, 1, 0, 0, 0, 7];
+        let limits = Limits { max_depth: 2, ..Limits::default() };
+        let mut de = BinaryProtocol::with_limits(Cursor::new(v.clone()), limits);
+        assert!(de.skip(ThriftType::List).is_ok());
+
+        let limits = Limits { max_depth: 1, ..Limits::default() };
+        let mut de = BinaryProtocol::with_limits(Cursor::new(v), limits);
+        match de.skip(ThriftType::List) {
+            Err(Error::DepthLimit) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn read_list_begin_over_limit() {
+        let limits = Limits { max_container_size: 2, ..Limits::default() };
+        let mut de = BinaryProtocol::with_limits(Cursor::new(vec![8, 0, 0, 0, 3]), limits);
+        match de.read_list_begin() {
+            Err(Error::ContainerSizeLimit(3)) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn read_field_begin_bad_type() {
+        let mut de = BinaryProtocol::new(Cursor::new(vec![7, 0, 1]));
+        match de.read_field_begin() {
+            Err(Error::BadType(7)) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
    #[test]
     fn serialize_bool_true() {
         let mut v: Vec<u8> = Vec::new();
@@ -479,14 +718,16 @@ mod tests {
         let mut v = Vec::new();
         {
             let mut proto = BinaryProtocol::new(&mut v);
-            assert!(proto.write_message_begin("foobar", ThriftMessageType::Call).is_ok());
+            assert!(proto.write_message_begin("foobar", ThriftMessageType::Call, 0x10203).is_ok());
         }
 
         let mut cursor = Cursor::new(v);
         let version = THRIFT_VERSION_1 | ThriftMessageType::Call as i32;
 
         assert_eq!(version, cursor.read_i32::<BigEndian>().unwrap());
-        // XXX Decode string and seqid.
+        assert_eq!(6, cursor.read_i32::<BigEndian>().unwrap());
+        cursor.set_position(4 + 4 + 6);
+        assert_eq!(0x10203, cursor.read_i32::<BigEndian>().unwrap());
     }
 
     #[test]
@@ -495,7 +736,7 @@ mod tests {
 
         {
             let mut se = BinaryProtocol::new(&mut buf);
-            assert!(se.write_message_begin("Foobar123", ThriftMessageType::Call).is_ok());
+            assert!(se.write_message_begin("Foobar123", ThriftMessageType::Call, -7).is_ok());
         }
 
         let mut de = BinaryProtocol::new(Cursor::new(buf));
@@ -503,5 +744,6 @@ mod tests {
 
         assert_eq!(msg.name, "Foobar123");
         assert_eq!(msg.ty, ThriftMessageType::Call);
+        assert_eq!(msg.seq, -7);
     }
 }
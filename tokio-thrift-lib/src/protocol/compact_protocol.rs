@@ -0,0 +1,747 @@
+use super::{Limits, Serializer, Deserializer, ThriftSerializer, ThriftField, ThriftList, ThriftSet, ThriftMap, ThriftMessage, ThriftDeserializer, ThriftMessageType, ThriftType, Error};
+use transport::{VoidTransport, ReadTransport, WriteTransport};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+pub const COMPACT_PROTOCOL_ID: u8 = 0x82;
+pub const COMPACT_VERSION: u8 = 1;
+pub const COMPACT_VERSION_MASK: u8 = 0x1f;
+pub const COMPACT_TYPE_MASK: u8 = 0xe0;
+pub const COMPACT_TYPE_SHIFT_AMOUNT: u8 = 5;
+
+const COMPACT_STOP: u8 = 0x00;
+const COMPACT_BOOLEAN_TRUE: u8 = 0x01;
+const COMPACT_BOOLEAN_FALSE: u8 = 0x02;
+const COMPACT_BYTE: u8 = 0x03;
+const COMPACT_I16: u8 = 0x04;
+const COMPACT_I32: u8 = 0x05;
+const COMPACT_I64: u8 = 0x06;
+const COMPACT_DOUBLE: u8 = 0x07;
+const COMPACT_BINARY: u8 = 0x08;
+const COMPACT_LIST: u8 = 0x09;
+const COMPACT_SET: u8 = 0x0A;
+const COMPACT_MAP: u8 = 0x0B;
+const COMPACT_STRUCT: u8 = 0x0C;
+
+/// Thrift compact protocol: zigzag varints for integers and
+/// field headers delta-encoded against the previous field id.
+pub struct CompactProtocol<T> {
+    inner: T,
+    last_field_id: i16,
+    last_field_ids: Vec<i16>,
+    // a bool field header is written together with its value
+    pending_write_bool: Option<i16>,
+    pending_read_bool: Option<bool>,
+    limits: Limits,
+}
+
+impl <T>CompactProtocol<T> {
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl <T: VoidTransport>CompactProtocol<T> {
+    pub fn new(inner: T) -> Self {
+        Self::with_limits(inner, Limits::default())
+    }
+
+    pub fn with_limits(inner: T, limits: Limits) -> Self {
+        CompactProtocol {
+            inner: inner,
+            last_field_id: 0,
+            last_field_ids: Vec::new(),
+            pending_write_bool: None,
+            pending_read_bool: None,
+            limits: limits,
+        }
+    }
+}
+
+impl <T: VoidTransport>From<T> for CompactProtocol<T> {
+    fn from(w: T) -> Self {
+        Self::new(w)
+    }
+}
+
+fn to_compact_type(ty: ThriftType) -> Result<u8, Error> {
+    match ty {
+        ThriftType::Stop => Ok(COMPACT_STOP),
+        ThriftType::Bool => Ok(COMPACT_BOOLEAN_TRUE),
+        ThriftType::Byte => Ok(COMPACT_BYTE),
+        ThriftType::I16 => Ok(COMPACT_I16),
+        ThriftType::I32 => Ok(COMPACT_I32),
+        ThriftType::U64 | ThriftType::I64 => Ok(COMPACT_I64),
+        ThriftType::Double => Ok(COMPACT_DOUBLE),
+        ThriftType::String => Ok(COMPACT_BINARY),
+        ThriftType::List => Ok(COMPACT_LIST),
+        ThriftType::Set => Ok(COMPACT_SET),
+        ThriftType::Map => Ok(COMPACT_MAP),
+        ThriftType::Struct => Ok(COMPACT_STRUCT),
+        ty => Err(Error::BadType(ty as i8)),
+    }
+}
+
+fn from_compact_type(val: u8) -> Result<ThriftType, Error> {
+    match val {
+        COMPACT_STOP => Ok(ThriftType::Stop),
+        COMPACT_BOOLEAN_TRUE | COMPACT_BOOLEAN_FALSE => Ok(ThriftType::Bool),
+        COMPACT_BYTE => Ok(ThriftType::Byte),
+        COMPACT_I16 => Ok(ThriftType::I16),
+        COMPACT_I32 => Ok(ThriftType::I32),
+        COMPACT_I64 => Ok(ThriftType::I64),
+        COMPACT_DOUBLE => Ok(ThriftType::Double),
+        COMPACT_BINARY => Ok(ThriftType::String),
+        COMPACT_LIST => Ok(ThriftType::List),
+        COMPACT_SET => Ok(ThriftType::Set),
+        COMPACT_MAP => Ok(ThriftType::Map),
+        COMPACT_STRUCT => Ok(ThriftType::Struct),
+        e => Err(Error::BadType(e as i8)),
+    }
+}
+
+fn i32_to_zigzag(n: i32) -> u32 {
+    ((n << 1) ^ (n >> 31)) as u32
+}
+
+fn zigzag_to_i32(n: u32) -> i32 {
+    ((n >> 1) as i32) ^ -((n & 1) as i32)
+}
+
+fn i64_to_zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+fn zigzag_to_i64(n: u64) -> i64 {
+    ((n >> 1) as i64) ^ -((n & 1) as i64)
+}
+
+impl <T: WriteTransport>CompactProtocol<T> {
+    fn write_varint(&mut self, mut n: u64) -> Result<(), Error> {
+        loop {
+            if n & !0x7f == 0 {
+                try!(self.inner.write_u8(n as u8));
+                return Ok(());
+            }
+            try!(self.inner.write_u8(((n & 0x7f) | 0x80) as u8));
+            n >>= 7;
+        }
+    }
+
+    fn write_field_header(&mut self, ty: u8, id: i16) -> Result<(), Error> {
+        // in i32, as ids far apart on either side of zero overflow an i16
+        let delta = id as i32 - self.last_field_id as i32;
+        if delta > 0 && delta <= 15 {
+            try!(self.inner.write_u8(((delta as u8) << 4) | ty));
+        } else {
+            try!(self.inner.write_u8(ty));
+            try!(self.serialize_i16(id));
+        }
+        self.last_field_id = id;
+        Ok(())
+    }
+
+    // lists and sets share a header: short sizes are packed next to the element type
+    fn write_collection_begin(&mut self, ty: ThriftType, size: usize) -> Result<(), Error> {
+        let ty = try!(to_compact_type(ty));
+        if size < 15 {
+            try!(self.inner.write_u8(((size as u8) << 4) | ty));
+        } else {
+            try!(self.inner.write_u8(0xf0 | ty));
+            try!(self.write_varint(size as u64));
+        }
+        Ok(())
+    }
+}
+
+impl <T: WriteTransport> Serializer for CompactProtocol<T> {
+
+    fn serialize_bool(&mut self, val: bool) -> Result<(), Error> {
+        let ty = if val { COMPACT_BOOLEAN_TRUE } else { COMPACT_BOOLEAN_FALSE };
+        match self.pending_write_bool.take() {
+            Some(id) => self.write_field_header(ty, id),
+            None => {
+                try!(self.inner.write_u8(ty));
+                Ok(())
+            }
+        }
+    }
+
+    fn serialize_usize(&mut self, val: usize) -> Result<(), Error> {
+        self.serialize_isize(val as isize)
+    }
+
+    fn serialize_isize(&mut self, val: isize) -> Result<(), Error> {
+        self.serialize_i64(val as i64)
+    }
+
+    fn serialize_u64(&mut self, val: u64) -> Result<(), Error> {
+        self.serialize_i64(val as i64)
+    }
+
+    fn serialize_i64(&mut self, val: i64) -> Result<(), Error> {
+        self.write_varint(i64_to_zigzag(val))
+    }
+
+    fn serialize_u32(&mut self, val: u32) -> Result<(), Error> {
+        self.serialize_i32(val as i32)
+    }
+
+    fn serialize_i32(&mut self, val: i32) -> Result<(), Error> {
+        self.write_varint(i32_to_zigzag(val) as u64)
+    }
+
+    fn serialize_u16(&mut self, val: u16) -> Result<(), Error> {
+        self.serialize_i16(val as i16)
+    }
+
+    fn serialize_i16(&mut self, val: i16) -> Result<(), Error> {
+        self.serialize_i32(val as i32)
+    }
+
+    fn serialize_u8(&mut self, val: u8) -> Result<(), Error> {
+        self.serialize_i8(val as i8)
+    }
+
+    fn serialize_i8(&mut self, val: i8) -> Result<(), Error> {
+        try!(self.inner.write_i8(val));
+        Ok(())
+    }
+
+    fn serialize_f64(&mut self, val: f64) -> Result<(), Error> {
+        try!(self.inner.write_f64::<LittleEndian>(val));
+        Ok(())
+    }
+
+    fn serialize_bytes(&mut self, val: &[u8]) -> Result<(), Error> {
+        try!(self.write_varint(val.len() as u64));
+        try!(self.inner.write_all(val));
+        Ok(())
+    }
+
+    fn serialize_str(&mut self, val: &str) -> Result<(), Error> {
+        self.serialize_bytes(val.as_bytes())
+    }
+
+    fn serialize_string(&mut self, val: String) -> Result<(), Error> {
+        self.serialize_str(&*val)
+    }
+}
+
+impl <T: WriteTransport>ThriftSerializer for CompactProtocol<T> {
+    fn write_message_begin(&mut self, name: &str, message_type: ThriftMessageType, seq: i32) -> Result<(), Error> {
+        try!(self.inner.write_u8(COMPACT_PROTOCOL_ID));
+        try!(self.inner.write_u8((COMPACT_VERSION & COMPACT_VERSION_MASK) |
+                                 (((message_type as u8) << COMPACT_TYPE_SHIFT_AMOUNT) & COMPACT_TYPE_MASK)));
+        // seqid is a plain varint, not zigzag encoded
+        try!(self.write_varint(seq as u32 as u64));
+        try!(self.serialize_str(name));
+        Ok(())
+    }
+
+    fn write_struct_begin(&mut self, _name: &str) -> Result<(), Error> {
+        self.last_field_ids.push(self.last_field_id);
+        self.last_field_id = 0;
+        Ok(())
+    }
+
+    fn write_struct_end(&mut self) -> Result<(), Error> {
+        self.last_field_id = self.last_field_ids.pop().unwrap_or(0);
+        Ok(())
+    }
+
+    fn write_field_begin(&mut self, _name: &str, ty: ThriftType, id: i16) -> Result<(), Error> {
+        if ty == ThriftType::Bool {
+            self.pending_write_bool = Some(id);
+            Ok(())
+        } else {
+            let ty = try!(to_compact_type(ty));
+            self.write_field_header(ty, id)
+        }
+    }
+
+    fn write_field_end(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn write_field_stop(&mut self) -> Result<(), Error> {
+        try!(self.inner.write_u8(COMPACT_STOP));
+        Ok(())
+    }
+
+    fn write_list_begin(&mut self, ty: ThriftType, size: usize) -> Result<(), Error> {
+        self.write_collection_begin(ty, size)
+    }
+
+    fn write_set_begin(&mut self, ty: ThriftType, size: usize) -> Result<(), Error> {
+        self.write_collection_begin(ty, size)
+    }
+
+    fn write_map_begin(&mut self, key_ty: ThriftType, value_ty: ThriftType, size: usize) -> Result<(), Error> {
+        try!(self.write_varint(size as u64));
+        if size > 0 {
+            let key_ty = try!(to_compact_type(key_ty));
+            let value_ty = try!(to_compact_type(value_ty));
+            try!(self.inner.write_u8((key_ty << 4) | value_ty));
+        }
+        Ok(())
+    }
+
+    fn write_message_end(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+
+impl <T: ReadTransport>CompactProtocol<T> {
+    fn read_varint(&mut self) -> Result<u64, Error> {
+        let mut ret = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = try!(self.inner.read_u8());
+            ret |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(ret);
+            }
+            shift += 7;
+            if shift > 63 {
+                return Err(Error::BadVarint);
+            }
+        }
+    }
+
+    fn read_container_size(&mut self, size: u64) -> Result<usize, Error> {
+        if size > self.limits.max_container_size as u64 {
+            return Err(Error::ContainerSizeLimit(size as usize));
+        }
+        Ok(size as usize)
+    }
+
+    fn read_collection_begin(&mut self) -> Result<(ThriftType, usize), Error> {
+        let header = try!(self.inner.read_u8());
+        let ty = try!(from_compact_type(header & 0x0f));
+        let size = match header >> 4 {
+            15 => try!(self.read_varint()),
+            size => size as u64,
+        };
+        Ok((ty, try!(self.read_container_size(size))))
+    }
+}
+
+impl<T: ReadTransport> Deserializer for CompactProtocol<T> {
+    fn deserialize_bool(&mut self) -> Result<bool, Error> {
+        match self.pending_read_bool.take() {
+            Some(b) => Ok(b),
+            None => Ok(try!(self.inner.read_u8()) == COMPACT_BOOLEAN_TRUE),
+        }
+    }
+
+    fn deserialize_usize(&mut self) -> Result<usize, Error> {
+        Ok(try!(self.deserialize_isize()) as usize)
+    }
+
+    fn deserialize_isize(&mut self) -> Result<isize, Error> {
+        Ok(try!(self.deserialize_i64()) as isize)
+    }
+
+    fn deserialize_u64(&mut self) -> Result<u64, Error> {
+        Ok(try!(self.deserialize_i64()) as u64)
+    }
+
+    fn deserialize_i64(&mut self) -> Result<i64, Error> {
+        Ok(zigzag_to_i64(try!(self.read_varint())))
+    }
+
+    fn deserialize_u32(&mut self) -> Result<u32, Error> {
+        Ok(try!(self.deserialize_i32()) as u32)
+    }
+
+    fn deserialize_i32(&mut self) -> Result<i32, Error> {
+        Ok(zigzag_to_i32(try!(self.read_varint()) as u32))
+    }
+
+    fn deserialize_u16(&mut self) -> Result<u16, Error> {
+        Ok(try!(self.deserialize_i16()) as u16)
+    }
+
+    fn deserialize_i16(&mut self) -> Result<i16, Error> {
+        Ok(try!(self.deserialize_i32()) as i16)
+    }
+
+    fn deserialize_u8(&mut self) -> Result<u8, Error> {
+        Ok(try!(self.deserialize_i8()) as u8)
+    }
+
+    fn deserialize_i8(&mut self) -> Result<i8, Error> {
+        Ok(try!(self.inner.read_i8()))
+    }
+
+    fn deserialize_f64(&mut self) -> Result<f64, Error> {
+        Ok(try!(self.inner.read_f64::<LittleEndian>()))
+    }
+
+    fn deserialize_bytes(&mut self) -> Result<Vec<u8>, Error> {
+        let len = try!(self.read_varint()) as usize;
+        try!(self.limits.check_string_size(len));
+        let mut buf = vec![0; len];
+        try!(self.inner.read_exact(&mut buf));
+        Ok(buf)
+    }
+
+    fn deserialize_str(&mut self) -> Result<String, Error> {
+        let buf = try!(self.deserialize_bytes());
+        let s = try!(String::from_utf8(buf));
+        Ok(s)
+    }
+}
+
+impl<T: ReadTransport> ThriftDeserializer for CompactProtocol<T> {
+    fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    fn read_message_begin(&mut self) -> Result<ThriftMessage, Error> {
+        let protocol_id = try!(self.inner.read_u8());
+        if protocol_id != COMPACT_PROTOCOL_ID {
+            return Err(Error::ProtocolVersionMissing);
+        }
+        let version_and_type = try!(self.inner.read_u8());
+        if version_and_type & COMPACT_VERSION_MASK != COMPACT_VERSION {
+            return Err(Error::BadVersion);
+        }
+        let ty = (version_and_type & COMPACT_TYPE_MASK) >> COMPACT_TYPE_SHIFT_AMOUNT;
+        let seq = try!(self.read_varint()) as u32 as i32;
+        Ok(ThriftMessage {
+            ty: try!(ThriftMessageType::from_i8(ty as i8)),
+            seq: seq,
+            name: try!(self.deserialize_str()),
+        })
+    }
+
+    fn read_message_end(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn read_struct_begin(&mut self) -> Result<String, Error> {
+        try!(self.limits.check_depth(self.last_field_ids.len() + 1));
+        self.last_field_ids.push(self.last_field_id);
+        self.last_field_id = 0;
+        Ok("".to_string())
+    }
+
+    fn read_struct_end(&mut self) -> Result<(), Error> {
+        self.last_field_id = self.last_field_ids.pop().unwrap_or(0);
+        Ok(())
+    }
+
+    fn read_field_begin(&mut self) -> Result<ThriftField, Error> {
+        let header = try!(self.inner.read_u8());
+        let ty = header & 0x0f;
+        if ty == COMPACT_STOP {
+            return Ok(ThriftField {
+                name: None,
+                ty: ThriftType::Stop,
+                seq: 0,
+            });
+        }
+
+        let delta = (header & 0xf0) >> 4;
+        let seq = if delta == 0 {
+            try!(self.deserialize_i16())
+        } else {
+            match self.last_field_id.checked_add(delta as i16) {
+                Some(seq) => seq,
+                None => return Err(Error::BadFieldId),
+            }
+        };
+        match ty {
+            COMPACT_BOOLEAN_TRUE => self.pending_read_bool = Some(true),
+            COMPACT_BOOLEAN_FALSE => self.pending_read_bool = Some(false),
+            _ => (),
+        }
+        self.last_field_id = seq;
+
+        Ok(ThriftField {
+            name: None,
+            ty: try!(from_compact_type(ty)),
+            seq: seq,
+        })
+    }
+
+    fn read_field_end(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn read_list_begin(&mut self) -> Result<ThriftList, Error> {
+        let (ty, size) = try!(self.read_collection_begin());
+        Ok(ThriftList { ty: ty, size: size })
+    }
+
+    fn read_list_end(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn read_set_begin(&mut self) -> Result<ThriftSet, Error> {
+        let (ty, size) = try!(self.read_collection_begin());
+        Ok(ThriftSet { ty: ty, size: size })
+    }
+
+    fn read_set_end(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn read_map_begin(&mut self) -> Result<ThriftMap, Error> {
+        let size = try!(self.read_varint());
+        let size = try!(self.read_container_size(size));
+        if size == 0 {
+            return Ok(ThriftMap { key_ty: ThriftType::Stop, value_ty: ThriftType::Stop, size: 0 });
+        }
+        let types = try!(self.inner.read_u8());
+        Ok(ThriftMap {
+            key_ty: try!(from_compact_type(types >> 4)),
+            value_ty: try!(from_compact_type(types & 0x0f)),
+            size: size,
+        })
+    }
+
+    fn read_map_end(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::collections::HashMap;
+    use protocol::{ThriftMessageType, ThriftType, ThriftDeserializer, ThriftSerializer, Serializer, Serialize, Deserializer, Deserialize};
+    use super::*;
+
+    #[test]
+    fn zigzag() {
+        assert_eq!(i32_to_zigzag(0), 0);
+        assert_eq!(i32_to_zigzag(-1), 1);
+        assert_eq!(i32_to_zigzag(1), 2);
+        assert_eq!(i32_to_zigzag(-2), 3);
+        assert_eq!(i32_to_zigzag(i32::max_value()), 0xfffffffe);
+        assert_eq!(i32_to_zigzag(i32::min_value()), 0xffffffff);
+        assert_eq!(zigzag_to_i32(0xffffffff), i32::min_value());
+        assert_eq!(zigzag_to_i64(i64_to_zigzag(i64::min_value())), i64::min_value());
+        assert_eq!(zigzag_to_i64(i64_to_zigzag(-33000000)), -33000000);
+    }
+
+    #[test]
+    fn serialize_i32() {
+        let mut v = Vec::new();
+        {
+            let mut s = CompactProtocol::new(&mut v);
+            assert!(s.serialize_i32(300).is_ok());
+            assert!(s.serialize_i32(-1).is_ok());
+        }
+
+        // 300 zigzags to 600 = 0b100_1011000
+        assert_eq!(v, vec![0xd8, 0x04, 0x01]);
+    }
+
+    #[test]
+    fn serialize_f64_little_endian() {
+        let mut v = Vec::new();
+        {
+            let mut s = CompactProtocol::new(&mut v);
+            assert!(s.serialize_f64(1.0).is_ok());
+        }
+
+        assert_eq!(v, vec![0, 0, 0, 0, 0, 0, 0xf0, 0x3f]);
+    }
+
+    #[test]
+    fn serialize_string() {
+        let mut v = Vec::new();
+        {
+            let mut s = CompactProtocol::new(&mut v);
+            assert!(s.serialize_str("foo").is_ok());
+        }
+
+        assert_eq!(v, vec![3, b'f', b'o', b'o']);
+    }
+
+    #[test]
+    fn deserialize_i64() {
+        let mut de = CompactProtocol::new(Cursor::new(vec![0xff, 0xa8, 0xbc, 0x1f]));
+        let val: i64 = Deserialize::deserialize(&mut de).unwrap();
+        assert_eq!(val, -33000000);
+    }
+
+    #[test]
+    fn deserialize_truncated_string() {
+        let mut de = CompactProtocol::new(Cursor::new(vec![5, b'f', b'o']));
+        assert!(de.deserialize_str().is_err());
+    }
+
+    #[test]
+    fn list_header() {
+        let mut v = Vec::new();
+        assert!(vec![true, false].serialize(&mut CompactProtocol::new(&mut v)).is_ok());
+        assert_eq!(v, [0x21, 1, 2]);
+
+        let mut v = Vec::new();
+        assert!(vec![0i8; 20].serialize(&mut CompactProtocol::new(&mut v)).is_ok());
+        assert_eq!(&v[..2], [0xf3, 20]);
+        assert_eq!(v.len(), 22);
+    }
+
+    #[test]
+    fn map_round_trip() {
+        let empty: HashMap<i32, String> = HashMap::new();
+        let mut map = HashMap::new();
+        map.insert(1i32, "a".to_string());
+
+        let mut v = Vec::new();
+        {
+            let mut s = CompactProtocol::new(&mut v);
+            assert!(empty.serialize(&mut s).is_ok());
+            assert!(map.serialize(&mut s).is_ok());
+        }
+        assert_eq!(v, [0, 1, 0x58, 2, 1, b'a']);
+
+        let mut de = CompactProtocol::new(Cursor::new(v));
+        let empty2: HashMap<i32, String> = Deserialize::deserialize(&mut de).unwrap();
+        let map2: HashMap<i32, String> = Deserialize::deserialize(&mut de).unwrap();
+        assert_eq!(empty2, empty);
+        assert_eq!(map2, map);
+    }
+
+    #[test]
+    fn deserialize_bad_varint() {
+        let mut de = CompactProtocol::new(Cursor::new(vec![0xff; 11]));
+        match de.deserialize_i64() {
+            Err(Error::BadVarint) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn field_id_overflow() {
+        // field 32767 in long form with an i32 value, then one with a delta of 1
+        let mut de = CompactProtocol::new(Cursor::new(vec![0x05, 0xfe, 0xff, 0x03, 0x00, 0x15]));
+        assert!(de.read_struct_begin().is_ok());
+        assert_eq!(de.read_field_begin().unwrap().seq, 32767);
+        assert_eq!(de.deserialize_i32().unwrap(), 0);
+        match de.read_field_begin() {
+            Err(Error::BadFieldId) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn field_header_distant_ids() {
+        let mut v = Vec::new();
+        {
+            let mut s = CompactProtocol::new(&mut v);
+            assert!(s.write_struct_begin("Foo").is_ok());
+            assert!(s.write_field_begin("a", ThriftType::String, -1).is_ok());
+            assert!(s.serialize_str("").is_ok());
+            assert!(s.write_field_begin("b", ThriftType::I32, 32767).is_ok());
+            assert!(s.serialize_i32(0).is_ok());
+            assert!(s.write_field_stop().is_ok());
+            assert!(s.write_struct_end().is_ok());
+        }
+
+        // both in long form
+        assert_eq!(v, vec![0x08, 0x01, 0x00,
+                           0x05, 0xfe, 0xff, 0x03, 0x00,
+                           0x00]);
+
+        let mut de = CompactProtocol::new(Cursor::new(v));
+        assert!(de.read_struct_begin().is_ok());
+        assert_eq!(de.read_field_begin().unwrap().seq, -1);
+        assert_eq!(de.deserialize_str().unwrap(), "");
+        assert_eq!(de.read_field_begin().unwrap().seq, 32767);
+        assert_eq!(de.deserialize_i32().unwrap(), 0);
+        assert_eq!(de.read_field_begin().unwrap().ty, ThriftType::Stop);
+    }
+
+    #[test]
+    fn field_header_delta() {
+        let mut v = Vec::new();
+        {
+            let mut s = CompactProtocol::new(&mut v);
+            assert!(s.write_struct_begin("Foo").is_ok());
+            assert!(s.write_field_begin("a", ThriftType::I32, 1).is_ok());
+            assert!(s.serialize_i32(1).is_ok());
+            assert!(s.write_field_begin("b", ThriftType::String, 20).is_ok());
+            assert!(s.serialize_str("").is_ok());
+            assert!(s.write_field_begin("c", ThriftType::Bool, 21).is_ok());
+            assert!(s.serialize_bool(false).is_ok());
+            assert!(s.write_field_stop().is_ok());
+            assert!(s.write_struct_end().is_ok());
+        }
+
+        assert_eq!(v, vec![0x15, 0x02,
+                           0x08, 0x28, 0x00,
+                           0x12,
+                           0x00]);
+
+        let mut de = CompactProtocol::new(Cursor::new(v));
+        assert!(de.read_struct_begin().is_ok());
+        let field = de.read_field_begin().unwrap();
+        assert_eq!((field.ty, field.seq), (ThriftType::I32, 1));
+        assert_eq!(de.deserialize_i32().unwrap(), 1);
+        let field = de.read_field_begin().unwrap();
+        assert_eq!((field.ty, field.seq), (ThriftType::String, 20));
+        assert_eq!(de.deserialize_str().unwrap(), "");
+        let field = de.read_field_begin().unwrap();
+        assert_eq!((field.ty, field.seq), (ThriftType::Bool, 21));
+        assert_eq!(de.deserialize_bool().unwrap(), false);
+        assert_eq!(de.read_field_begin().unwrap().ty, ThriftType::Stop);
+        assert!(de.read_struct_end().is_ok());
+    }
+
+    #[test]
+    fn nested_struct_restores_field_id() {
+        let mut v = Vec::new();
+        {
+            let mut s = CompactProtocol::new(&mut v);
+            assert!(s.write_struct_begin("Outer").is_ok());
+            assert!(s.write_field_begin("inner", ThriftType::Struct, 3).is_ok());
+            assert!(s.write_struct_begin("Inner").is_ok());
+            assert!(s.write_field_begin("a", ThriftType::Byte, 1).is_ok());
+            assert!(s.serialize_i8(7).is_ok());
+            assert!(s.write_field_stop().is_ok());
+            assert!(s.write_struct_end().is_ok());
+            assert!(s.write_field_begin("b", ThriftType::I64, 4).is_ok());
+            assert!(s.serialize_i64(1).is_ok());
+            assert!(s.write_field_stop().is_ok());
+            assert!(s.write_struct_end().is_ok());
+        }
+
+        assert_eq!(v, vec![0x3c, 0x13, 0x07, 0x00, 0x16, 0x02, 0x00]);
+    }
+
+    #[test]
+    fn protocol_begin() {
+        let mut v = Vec::new();
+        {
+            let mut proto = CompactProtocol::new(&mut v);
+            assert!(proto.write_message_begin("foo", ThriftMessageType::Reply, 300).is_ok());
+        }
+
+        assert_eq!(v, vec![0x82, 0x41, 0xac, 0x02, 0x03, b'f', b'o', b'o']);
+    }
+
+    #[test]
+    fn write_and_read_message_begin() {
+        let mut buf = Vec::new();
+
+        {
+            let mut se = CompactProtocol::new(&mut buf);
+            assert!(se.write_message_begin("Foobar123", ThriftMessageType::Call, -1).is_ok());
+        }
+
+        let mut de = CompactProtocol::new(Cursor::new(buf));
+        let msg = de.read_message_begin().unwrap();
+
+        assert_eq!(msg.name, "Foobar123");
+        assert_eq!(msg.ty, ThriftMessageType::Call);
+        assert_eq!(msg.seq, -1);
+    }
+}
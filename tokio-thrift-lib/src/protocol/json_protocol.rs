@@ -0,0 +1,889 @@
+use super::{Limits, Serializer, Deserializer, ThriftSerializer, ThriftField, ThriftList, ThriftSet, ThriftMap, ThriftMessage, ThriftDeserializer, ThriftMessageType, ThriftType, Error};
+use transport::{VoidTransport, ReadTransport, WriteTransport};
+use byteorder::{ReadBytesExt, WriteBytesExt};
+use rustc_serialize::base64::{self, FromBase64, ToBase64};
+use std::char;
+use std::io::{self, Write};
+
+pub const JSON_VERSION: i64 = 1;
+
+pub(super) const BASE64_CONFIG: base64::Config = base64::Config {
+    char_set: base64::CharacterSet::Standard,
+    newline: base64::Newline::LF,
+    pad: false,
+    line_length: None,
+};
+
+/// Separators expected between the values of the enclosing JSON container.
+pub(super) enum Context {
+    Base,
+    List { first: bool },
+    // `colon` is true while the next value is an object key
+    Pair { first: bool, colon: bool },
+}
+
+impl Context {
+    pub(super) fn separator(&mut self) -> Option<u8> {
+        match *self {
+            Context::Base => None,
+            Context::List { ref mut first } => {
+                if *first {
+                    *first = false;
+                    None
+                } else {
+                    Some(b',')
+                }
+            }
+            Context::Pair { ref mut first, ref mut colon } => {
+                if *first {
+                    *first = false;
+                    *colon = true;
+                    None
+                } else {
+                    let sep = if *colon { b':' } else { b',' };
+                    *colon = !*colon;
+                    Some(sep)
+                }
+            }
+        }
+    }
+
+    // object keys are always strings, so numbers used as keys are quoted
+    pub(super) fn escape_num(&self) -> bool {
+        match *self {
+            Context::Pair { colon, .. } => colon,
+            _ => false,
+        }
+    }
+}
+
+pub(super) fn write_escaped<W: Write>(w: &mut W, val: &[u8]) -> io::Result<()> {
+    try!(w.write_u8(b'"'));
+    for &b in val {
+        match b {
+            b'"' => try!(w.write_all(b"\\\"")),
+            b'\\' => try!(w.write_all(b"\\\\")),
+            0x08 => try!(w.write_all(b"\\b")),
+            0x0c => try!(w.write_all(b"\\f")),
+            b'\n' => try!(w.write_all(b"\\n")),
+            b'\r' => try!(w.write_all(b"\\r")),
+            b'\t' => try!(w.write_all(b"\\t")),
+            b if b < 0x20 => try!(write!(w, "\\u{:04x}", b)),
+            b => try!(w.write_u8(b)),
+        }
+    }
+    w.write_u8(b'"')
+}
+
+pub(super) fn special_double(val: f64) -> Option<&'static str> {
+    if val.is_nan() {
+        Some("NaN")
+    } else if val == ::std::f64::INFINITY {
+        Some("Infinity")
+    } else if val == ::std::f64::NEG_INFINITY {
+        Some("-Infinity")
+    } else {
+        None
+    }
+}
+
+fn is_json_numeric(b: u8) -> bool {
+    match b {
+        b'+' | b'-' | b'.' | b'e' | b'E' => true,
+        b => b.is_ascii_digit(),
+    }
+}
+
+fn type_name(ty: ThriftType) -> Result<&'static str, Error> {
+    match ty {
+        ThriftType::Bool => Ok("tf"),
+        ThriftType::Byte => Ok("i8"),
+        ThriftType::I16 => Ok("i16"),
+        ThriftType::I32 => Ok("i32"),
+        ThriftType::U64 | ThriftType::I64 => Ok("i64"),
+        ThriftType::Double => Ok("dbl"),
+        ThriftType::String => Ok("str"),
+        ThriftType::Struct => Ok("rec"),
+        ThriftType::Map => Ok("map"),
+        ThriftType::Set => Ok("set"),
+        ThriftType::List => Ok("lst"),
+        ty => Err(Error::BadType(ty as i8)),
+    }
+}
+
+fn from_type_name(name: &str) -> Result<ThriftType, Error> {
+    match name {
+        "tf" => Ok(ThriftType::Bool),
+        "i8" => Ok(ThriftType::Byte),
+        "i16" => Ok(ThriftType::I16),
+        "i32" => Ok(ThriftType::I32),
+        "i64" => Ok(ThriftType::I64),
+        "dbl" => Ok(ThriftType::Double),
+        "str" => Ok(ThriftType::String),
+        "rec" => Ok(ThriftType::Struct),
+        "map" => Ok(ThriftType::Map),
+        "set" => Ok(ThriftType::Set),
+        "lst" => Ok(ThriftType::List),
+        name => Err(Error::InvalidJson(format!("unknown type name {:?}", name))),
+    }
+}
+
+/// Thrift JSON protocol, compatible with Apache's TJSONProtocol.
+///
+/// ```
+/// use tokio_thrift::protocol::{JsonProtocol, Serializer};
+///
+/// let mut buf = Vec::new();
+/// JsonProtocol::new(&mut buf).serialize_bytes(b"thrift").unwrap();
+/// assert_eq!(buf, b"\"dGhyaWZ0\"");
+/// ```
+pub struct JsonProtocol<T> {
+    inner: T,
+    contexts: Vec<Context>,
+    lookahead: Option<u8>,
+    limits: Limits,
+    depth: usize,
+}
+
+impl <T>JsonProtocol<T> {
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn context(&mut self) -> &mut Context {
+        self.contexts.last_mut().expect("tokio-thrift internal error: empty json context")
+    }
+
+    fn push_context(&mut self, context: Context) {
+        self.contexts.push(context);
+    }
+
+    fn pop_context(&mut self) {
+        if self.contexts.len() > 1 {
+            self.contexts.pop();
+        }
+    }
+
+    fn escape_num(&mut self) -> bool {
+        self.context().escape_num()
+    }
+}
+
+impl <T: VoidTransport>JsonProtocol<T> {
+    pub fn new(inner: T) -> Self {
+        Self::with_limits(inner, Limits::default())
+    }
+
+    /// String and binary limits apply to the escaped length on the wire.
+    pub fn with_limits(inner: T, limits: Limits) -> Self {
+        JsonProtocol {
+            inner: inner,
+            contexts: vec![Context::Base],
+            lookahead: None,
+            limits: limits,
+            depth: 0,
+        }
+    }
+}
+
+impl <T: VoidTransport>From<T> for JsonProtocol<T> {
+    fn from(w: T) -> Self {
+        Self::new(w)
+    }
+}
+
+impl <T: WriteTransport>JsonProtocol<T> {
+    fn write_context(&mut self) -> Result<(), Error> {
+        if let Some(sep) = self.context().separator() {
+            try!(self.inner.write_u8(sep));
+        }
+        Ok(())
+    }
+
+    fn write_json_string(&mut self, val: &[u8]) -> Result<(), Error> {
+        try!(self.write_context());
+        try!(write_escaped(&mut self.inner, val));
+        Ok(())
+    }
+
+    fn write_json_integer(&mut self, val: i64) -> Result<(), Error> {
+        try!(self.write_context());
+        if self.escape_num() {
+            try!(write!(self.inner, "\"{}\"", val));
+        } else {
+            try!(write!(self.inner, "{}", val));
+        }
+        Ok(())
+    }
+
+    fn write_json_double(&mut self, val: f64) -> Result<(), Error> {
+        try!(self.write_context());
+        match special_double(val) {
+            Some(s) => try!(write!(self.inner, "\"{}\"", s)),
+            None if self.escape_num() => try!(write!(self.inner, "\"{}\"", val)),
+            None => try!(write!(self.inner, "{}", val)),
+        }
+        Ok(())
+    }
+
+    fn write_json_object_start(&mut self) -> Result<(), Error> {
+        try!(self.write_context());
+        try!(self.inner.write_u8(b'{'));
+        self.push_context(Context::Pair { first: true, colon: true });
+        Ok(())
+    }
+
+    fn write_json_object_end(&mut self) -> Result<(), Error> {
+        self.pop_context();
+        try!(self.inner.write_u8(b'}'));
+        Ok(())
+    }
+
+    fn write_json_array_start(&mut self) -> Result<(), Error> {
+        try!(self.write_context());
+        try!(self.inner.write_u8(b'['));
+        self.push_context(Context::List { first: true });
+        Ok(())
+    }
+
+    fn write_json_array_end(&mut self) -> Result<(), Error> {
+        self.pop_context();
+        try!(self.inner.write_u8(b']'));
+        Ok(())
+    }
+}
+
+impl <T: WriteTransport> Serializer for JsonProtocol<T> {
+
+    fn serialize_bool(&mut self, val: bool) -> Result<(), Error> {
+        self.write_json_integer(if val { 1 } else { 0 })
+    }
+
+    fn serialize_usize(&mut self, val: usize) -> Result<(), Error> {
+        self.serialize_isize(val as isize)
+    }
+
+    fn serialize_isize(&mut self, val: isize) -> Result<(), Error> {
+        self.serialize_i64(val as i64)
+    }
+
+    fn serialize_u64(&mut self, val: u64) -> Result<(), Error> {
+        self.serialize_i64(val as i64)
+    }
+
+    fn serialize_i64(&mut self, val: i64) -> Result<(), Error> {
+        self.write_json_integer(val)
+    }
+
+    fn serialize_u32(&mut self, val: u32) -> Result<(), Error> {
+        self.serialize_i32(val as i32)
+    }
+
+    fn serialize_i32(&mut self, val: i32) -> Result<(), Error> {
+        self.write_json_integer(val as i64)
+    }
+
+    fn serialize_u16(&mut self, val: u16) -> Result<(), Error> {
+        self.serialize_i16(val as i16)
+    }
+
+    fn serialize_i16(&mut self, val: i16) -> Result<(), Error> {
+        self.write_json_integer(val as i64)
+    }
+
+    fn serialize_u8(&mut self, val: u8) -> Result<(), Error> {
+        self.serialize_i8(val as i8)
+    }
+
+    fn serialize_i8(&mut self, val: i8) -> Result<(), Error> {
+        self.write_json_integer(val as i64)
+    }
+
+    fn serialize_f64(&mut self, val: f64) -> Result<(), Error> {
+        self.write_json_double(val)
+    }
+
+    fn serialize_bytes(&mut self, val: &[u8]) -> Result<(), Error> {
+        let encoded = val.to_base64(BASE64_CONFIG);
+        self.write_json_string(encoded.as_bytes())
+    }
+
+    fn serialize_str(&mut self, val: &str) -> Result<(), Error> {
+        self.write_json_string(val.as_bytes())
+    }
+
+    fn serialize_string(&mut self, val: String) -> Result<(), Error> {
+        self.serialize_str(&*val)
+    }
+}
+
+impl <T: WriteTransport>ThriftSerializer for JsonProtocol<T> {
+    fn write_message_begin(&mut self, name: &str, message_type: ThriftMessageType, seq: i32) -> Result<(), Error> {
+        try!(self.write_json_array_start());
+        try!(self.write_json_integer(JSON_VERSION));
+        try!(self.write_json_string(name.as_bytes()));
+        try!(self.write_json_integer(message_type as i64));
+        try!(self.write_json_integer(seq as i64));
+        Ok(())
+    }
+
+    fn write_struct_begin(&mut self, _name: &str) -> Result<(), Error> {
+        self.write_json_object_start()
+    }
+
+    fn write_struct_end(&mut self) -> Result<(), Error> {
+        self.write_json_object_end()
+    }
+
+    fn write_field_begin(&mut self, _name: &str, ty: ThriftType, id: i16) -> Result<(), Error> {
+        try!(self.write_json_integer(id as i64));
+        try!(self.write_json_object_start());
+        let name = try!(type_name(ty));
+        self.write_json_string(name.as_bytes())
+    }
+
+    fn write_field_end(&mut self) -> Result<(), Error> {
+        self.write_json_object_end()
+    }
+
+    fn write_field_stop(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn write_list_begin(&mut self, ty: ThriftType, size: usize) -> Result<(), Error> {
+        try!(self.write_json_array_start());
+        try!(self.write_json_string(try!(type_name(ty)).as_bytes()));
+        self.write_json_integer(size as i64)
+    }
+
+    fn write_list_end(&mut self) -> Result<(), Error> {
+        self.write_json_array_end()
+    }
+
+    fn write_set_begin(&mut self, ty: ThriftType, size: usize) -> Result<(), Error> {
+        self.write_list_begin(ty, size)
+    }
+
+    fn write_set_end(&mut self) -> Result<(), Error> {
+        self.write_json_array_end()
+    }
+
+    fn write_map_begin(&mut self, key_ty: ThriftType, value_ty: ThriftType, size: usize) -> Result<(), Error> {
+        try!(self.write_json_array_start());
+        try!(self.write_json_string(try!(type_name(key_ty)).as_bytes()));
+        try!(self.write_json_string(try!(type_name(value_ty)).as_bytes()));
+        try!(self.write_json_integer(size as i64));
+        self.write_json_object_start()
+    }
+
+    fn write_map_end(&mut self) -> Result<(), Error> {
+        try!(self.write_json_object_end());
+        self.write_json_array_end()
+    }
+
+    fn write_message_end(&mut self) -> Result<(), Error> {
+        self.write_json_array_end()
+    }
+}
+
+
+impl <T: ReadTransport>JsonProtocol<T> {
+    fn peek(&mut self) -> Result<u8, Error> {
+        match self.lookahead {
+            Some(b) => Ok(b),
+            None => {
+                let b = try!(self.inner.read_u8());
+                self.lookahead = Some(b);
+                Ok(b)
+            }
+        }
+    }
+
+    fn read_byte(&mut self) -> Result<u8, Error> {
+        match self.lookahead.take() {
+            Some(b) => Ok(b),
+            None => Ok(try!(self.inner.read_u8())),
+        }
+    }
+
+    fn read_json_syntax_char(&mut self, expected: u8) -> Result<(), Error> {
+        let b = try!(self.read_byte());
+        if b == expected {
+            Ok(())
+        } else {
+            Err(Error::InvalidJson(format!("expected {:?} but found {:?}", expected as char, b as char)))
+        }
+    }
+
+    fn read_context(&mut self) -> Result<(), Error> {
+        if let Some(sep) = self.context().separator() {
+            try!(self.read_json_syntax_char(sep));
+        }
+        Ok(())
+    }
+
+    fn read_hex_escape(&mut self) -> Result<u32, Error> {
+        let mut val = 0;
+        for _ in 0..4 {
+            let b = try!(self.read_byte());
+            let digit = try!((b as char).to_digit(16)
+                .ok_or_else(|| Error::InvalidJson(format!("bad hex digit {:?}", b as char))));
+            val = (val << 4) | digit;
+        }
+        Ok(val)
+    }
+
+    fn read_json_string(&mut self, skip_context: bool) -> Result<Vec<u8>, Error> {
+        if !skip_context {
+            try!(self.read_context());
+        }
+        try!(self.read_json_syntax_char(b'"'));
+        let mut buf = Vec::new();
+        loop {
+            try!(self.limits.check_string_size(buf.len()));
+            match try!(self.read_byte()) {
+                b'"' => return Ok(buf),
+                b'\\' => {
+                    let escaped = match try!(self.read_byte()) {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let mut code = try!(self.read_hex_escape());
+                            if code >= 0xd800 && code < 0xdc00 {
+                                try!(self.read_json_syntax_char(b'\\'));
+                                try!(self.read_json_syntax_char(b'u'));
+                                let low = try!(self.read_hex_escape());
+                                if low < 0xdc00 || low >= 0xe000 {
+                                    return Err(Error::InvalidJson("missing low surrogate".to_string()));
+                                }
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                            }
+                            try!(char::from_u32(code)
+                                 .ok_or_else(|| Error::InvalidJson(format!("bad unicode escape {:x}", code))))
+                        }
+                        b => return Err(Error::InvalidJson(format!("bad escape {:?}", b as char))),
+                    };
+                    let mut utf8 = String::new();
+                    utf8.push(escaped);
+                    buf.extend_from_slice(utf8.as_bytes());
+                }
+                b => buf.push(b),
+            }
+        }
+    }
+
+    fn read_json_numeric_chars(&mut self) -> Result<String, Error> {
+        let mut s = String::new();
+        loop {
+            match self.peek() {
+                Ok(b) if is_json_numeric(b) => {
+                    s.push(b as char);
+                    self.lookahead = None;
+                }
+                // a bare number may be the last thing in the stream
+                Err(Error::EOF) if !s.is_empty() => return Ok(s),
+                Err(e) => return Err(e),
+                Ok(_) => return Ok(s),
+            }
+        }
+    }
+
+    fn read_json_integer(&mut self) -> Result<i64, Error> {
+        try!(self.read_context());
+        let escape = self.escape_num();
+        if escape {
+            try!(self.read_json_syntax_char(b'"'));
+        }
+        let s = try!(self.read_json_numeric_chars());
+        if escape {
+            try!(self.read_json_syntax_char(b'"'));
+        }
+        s.parse().map_err(|_| Error::InvalidJson(format!("bad integer {:?}", s)))
+    }
+
+    fn read_json_double(&mut self) -> Result<f64, Error> {
+        try!(self.read_context());
+        if try!(self.peek()) == b'"' {
+            let s = try!(String::from_utf8(try!(self.read_json_string(true))));
+            match &*s {
+                "NaN" => Ok(::std::f64::NAN),
+                "Infinity" => Ok(::std::f64::INFINITY),
+                "-Infinity" => Ok(::std::f64::NEG_INFINITY),
+                _ if self.escape_num() => s.parse().map_err(|_| Error::InvalidJson(format!("bad double {:?}", s))),
+                _ => Err(Error::InvalidJson(format!("numeric data unexpectedly quoted: {:?}", s))),
+            }
+        } else {
+            let s = try!(self.read_json_numeric_chars());
+            s.parse().map_err(|_| Error::InvalidJson(format!("bad double {:?}", s)))
+        }
+    }
+
+    fn read_json_object_start(&mut self) -> Result<(), Error> {
+        try!(self.read_context());
+        try!(self.read_json_syntax_char(b'{'));
+        self.push_context(Context::Pair { first: true, colon: true });
+        Ok(())
+    }
+
+    fn read_json_object_end(&mut self) -> Result<(), Error> {
+        try!(self.read_json_syntax_char(b'}'));
+        self.pop_context();
+        Ok(())
+    }
+
+    fn read_json_array_start(&mut self) -> Result<(), Error> {
+        try!(self.read_context());
+        try!(self.read_json_syntax_char(b'['));
+        self.push_context(Context::List { first: true });
+        Ok(())
+    }
+
+    fn read_json_array_end(&mut self) -> Result<(), Error> {
+        try!(self.read_json_syntax_char(b']'));
+        self.pop_context();
+        Ok(())
+    }
+
+    fn read_json_type(&mut self) -> Result<ThriftType, Error> {
+        let name = try!(String::from_utf8(try!(self.read_json_string(false))));
+        from_type_name(&name)
+    }
+
+    fn read_container_size(&mut self) -> Result<usize, Error> {
+        let size = try!(self.read_json_integer());
+        if size < 0 || size > i32::max_value() as i64 {
+            return Err(Error::InvalidJson(format!("bad container size {}", size)));
+        }
+        try!(self.limits.check_container_size(size as usize));
+        Ok(size as usize)
+    }
+}
+
+impl<T: ReadTransport> Deserializer for JsonProtocol<T> {
+    fn deserialize_bool(&mut self) -> Result<bool, Error> {
+        Ok(try!(self.read_json_integer()) != 0)
+    }
+
+    fn deserialize_usize(&mut self) -> Result<usize, Error> {
+        Ok(try!(self.deserialize_isize()) as usize)
+    }
+
+    fn deserialize_isize(&mut self) -> Result<isize, Error> {
+        Ok(try!(self.deserialize_i64()) as isize)
+    }
+
+    fn deserialize_u64(&mut self) -> Result<u64, Error> {
+        Ok(try!(self.deserialize_i64()) as u64)
+    }
+
+    fn deserialize_i64(&mut self) -> Result<i64, Error> {
+        self.read_json_integer()
+    }
+
+    fn deserialize_u32(&mut self) -> Result<u32, Error> {
+        Ok(try!(self.deserialize_i32()) as u32)
+    }
+
+    fn deserialize_i32(&mut self) -> Result<i32, Error> {
+        Ok(try!(self.read_json_integer()) as i32)
+    }
+
+    fn deserialize_u16(&mut self) -> Result<u16, Error> {
+        Ok(try!(self.deserialize_i16()) as u16)
+    }
+
+    fn deserialize_i16(&mut self) -> Result<i16, Error> {
+        Ok(try!(self.read_json_integer()) as i16)
+    }
+
+    fn deserialize_u8(&mut self) -> Result<u8, Error> {
+        Ok(try!(self.deserialize_i8()) as u8)
+    }
+
+    fn deserialize_i8(&mut self) -> Result<i8, Error> {
+        Ok(try!(self.read_json_integer()) as i8)
+    }
+
+    fn deserialize_f64(&mut self) -> Result<f64, Error> {
+        self.read_json_double()
+    }
+
+    fn deserialize_bytes(&mut self) -> Result<Vec<u8>, Error> {
+        let encoded = try!(self.read_json_string(false));
+        encoded.from_base64().map_err(|e| Error::InvalidJson(format!("bad base64: {}", e)))
+    }
+
+    fn deserialize_str(&mut self) -> Result<String, Error> {
+        let buf = try!(self.read_json_string(false));
+        let s = try!(String::from_utf8(buf));
+        Ok(s)
+    }
+
+    // binary is base64 and strings are plain text, both skip as a json string
+    fn skip_bytes(&mut self) -> Result<(), Error> {
+        self.read_json_string(false).map(|_| ())
+    }
+}
+
+impl<T: ReadTransport> ThriftDeserializer for JsonProtocol<T> {
+    fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    fn read_message_begin(&mut self) -> Result<ThriftMessage, Error> {
+        try!(self.read_json_array_start());
+        if try!(self.read_json_integer()) != JSON_VERSION {
+            return Err(Error::BadVersion);
+        }
+        let name = try!(String::from_utf8(try!(self.read_json_string(false))));
+        let ty = try!(self.read_json_integer());
+        let seq = try!(self.read_json_integer());
+        Ok(ThriftMessage {
+            name: name,
+            ty: try!(ThriftMessageType::from_i8(ty as i8)),
+            seq: seq as i32,
+        })
+    }
+
+    fn read_message_end(&mut self) -> Result<(), Error> {
+        self.read_json_array_end()
+    }
+
+    fn read_struct_begin(&mut self) -> Result<String, Error> {
+        self.depth += 1;
+        try!(self.limits.check_depth(self.depth));
+        try!(self.read_json_object_start());
+        Ok("".to_string())
+    }
+
+    fn read_struct_end(&mut self) -> Result<(), Error> {
+        self.depth = self.depth.saturating_sub(1);
+        self.read_json_object_end()
+    }
+
+    fn read_field_begin(&mut self) -> Result<ThriftField, Error> {
+        if try!(self.peek()) == b'}' {
+            return Ok(ThriftField {
+                name: None,
+                ty: ThriftType::Stop,
+                seq: 0,
+            });
+        }
+        let seq = try!(self.read_json_integer());
+        try!(self.read_json_object_start());
+        Ok(ThriftField {
+            name: None,
+            ty: try!(self.read_json_type()),
+            seq: seq as i16,
+        })
+    }
+
+    fn read_field_end(&mut self) -> Result<(), Error> {
+        self.read_json_object_end()
+    }
+
+    fn read_list_begin(&mut self) -> Result<ThriftList, Error> {
+        try!(self.read_json_array_start());
+        let ty = try!(self.read_json_type());
+        Ok(ThriftList {
+            ty: ty,
+            size: try!(self.read_container_size()),
+        })
+    }
+
+    fn read_list_end(&mut self) -> Result<(), Error> {
+        self.read_json_array_end()
+    }
+
+    fn read_set_begin(&mut self) -> Result<ThriftSet, Error> {
+        let list = try!(self.read_list_begin());
+        Ok(ThriftSet {
+            ty: list.ty,
+            size: list.size,
+        })
+    }
+
+    fn read_set_end(&mut self) -> Result<(), Error> {
+        self.read_json_array_end()
+    }
+
+    fn read_map_begin(&mut self) -> Result<ThriftMap, Error> {
+        try!(self.read_json_array_start());
+        let key_ty = try!(self.read_json_type());
+        let value_ty = try!(self.read_json_type());
+        let size = try!(self.read_container_size());
+        try!(self.read_json_object_start());
+        Ok(ThriftMap {
+            key_ty: key_ty,
+            value_ty: value_ty,
+            size: size,
+        })
+    }
+
+    fn read_map_end(&mut self) -> Result<(), Error> {
+        try!(self.read_json_object_end());
+        self.read_json_array_end()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::collections::BTreeMap;
+    use protocol::{ThriftMessageType, ThriftType, ThriftDeserializer, ThriftSerializer, Serializer, Serialize, Deserializer, Deserialize};
+    use super::*;
+
+    fn write_foo<S: Serializer + ThriftSerializer>(s: &mut S) {
+        assert!(s.write_message_begin("foo", ThriftMessageType::Call, 42).is_ok());
+        assert!(s.write_struct_begin("foo_args").is_ok());
+        assert!(s.write_field_begin("a", ThriftType::I32, 1).is_ok());
+        assert!(s.serialize_i32(-3).is_ok());
+        assert!(s.write_field_end().is_ok());
+        assert!(s.write_field_begin("b", ThriftType::String, 2).is_ok());
+        assert!(s.serialize_str("x\"y\n").is_ok());
+        assert!(s.write_field_end().is_ok());
+        assert!(s.write_field_begin("c", ThriftType::Double, 3).is_ok());
+        assert!(s.serialize_f64(0.5).is_ok());
+        assert!(s.write_field_end().is_ok());
+        assert!(s.write_field_stop().is_ok());
+        assert!(s.write_struct_end().is_ok());
+        assert!(s.write_message_end().is_ok());
+    }
+
+    #[test]
+    fn write_message() {
+        let mut v = Vec::new();
+        write_foo(&mut JsonProtocol::new(&mut v));
+
+        assert_eq!(String::from_utf8(v).unwrap(),
+                   r#"[1,"foo",1,42,{"1":{"i32":-3},"2":{"str":"x\"y\n"},"3":{"dbl":0.5}}]"#);
+    }
+
+    #[test]
+    fn write_and_read_message() {
+        let mut v = Vec::new();
+        write_foo(&mut JsonProtocol::new(&mut v));
+
+        let mut de = JsonProtocol::new(Cursor::new(v));
+        let msg = de.read_message_begin().unwrap();
+        assert_eq!(msg.name, "foo");
+        assert_eq!(msg.ty, ThriftMessageType::Call);
+        assert_eq!(msg.seq, 42);
+        assert!(de.read_struct_begin().is_ok());
+        let field = de.read_field_begin().unwrap();
+        assert_eq!((field.ty, field.seq), (ThriftType::I32, 1));
+        assert_eq!(de.deserialize_i32().unwrap(), -3);
+        assert!(de.read_field_end().is_ok());
+        let field = de.read_field_begin().unwrap();
+        assert_eq!((field.ty, field.seq), (ThriftType::String, 2));
+        assert_eq!(de.deserialize_str().unwrap(), "x\"y\n");
+        assert!(de.read_field_end().is_ok());
+        let field = de.read_field_begin().unwrap();
+        assert_eq!((field.ty, field.seq), (ThriftType::Double, 3));
+        assert_eq!(de.deserialize_f64().unwrap(), 0.5);
+        assert!(de.read_field_end().is_ok());
+        assert_eq!(de.read_field_begin().unwrap().ty, ThriftType::Stop);
+        assert!(de.read_struct_end().is_ok());
+        assert!(de.read_message_end().is_ok());
+    }
+
+    #[test]
+    fn base64_round_trip() {
+        for len in 0..5 {
+            let bytes: Vec<u8> = (0..len).map(|i| 0xf0 + i as u8).collect();
+            let mut v = Vec::new();
+            assert!(JsonProtocol::new(&mut v).serialize_bytes(&bytes).is_ok());
+            assert!(!v.contains(&b'='));
+            let mut de = JsonProtocol::new(Cursor::new(v));
+            assert_eq!(de.deserialize_bytes().unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn read_padded_base64() {
+        let mut de = JsonProtocol::new(Cursor::new(b"\"dGhyaWZ0cw==\"".to_vec()));
+        assert_eq!(de.deserialize_bytes().unwrap(), b"thrifts");
+    }
+
+    #[test]
+    fn read_unicode_escape() {
+        let mut de = JsonProtocol::new(Cursor::new(r#""é😀\/""#.as_bytes().to_vec()));
+        assert_eq!(de.deserialize_str().unwrap(), "\u{e9}\u{1f600}/");
+    }
+
+    #[test]
+    fn special_doubles() {
+        let mut v = Vec::new();
+        {
+            let mut s = JsonProtocol::new(&mut v);
+            assert!(s.write_message_begin("d", ThriftMessageType::Call, 0).is_ok());
+            assert!(s.serialize_f64(::std::f64::INFINITY).is_ok());
+            assert!(s.serialize_f64(::std::f64::NEG_INFINITY).is_ok());
+            assert!(s.serialize_f64(::std::f64::NAN).is_ok());
+            assert!(s.write_message_end().is_ok());
+        }
+        assert_eq!(String::from_utf8(v.clone()).unwrap(),
+                   r#"[1,"d",1,0,"Infinity","-Infinity","NaN"]"#);
+
+        let mut de = JsonProtocol::new(Cursor::new(v));
+        assert!(de.read_message_begin().is_ok());
+        assert_eq!(de.deserialize_f64().unwrap(), ::std::f64::INFINITY);
+        assert_eq!(de.deserialize_f64().unwrap(), ::std::f64::NEG_INFINITY);
+        assert!(de.deserialize_f64().unwrap().is_nan());
+    }
+
+    #[test]
+    fn containers() {
+        let mut map = BTreeMap::new();
+        map.insert(1i32, vec!["a".to_string()]);
+        map.insert(2i32, vec![]);
+        let mut v = Vec::new();
+        assert!(map.serialize(&mut JsonProtocol::new(&mut v)).is_ok());
+        assert_eq!(String::from_utf8(v.clone()).unwrap(),
+                   r#"["i32","lst",2,{"1":["str",1,"a"],"2":["str",0]}]"#);
+
+        let mut de = JsonProtocol::new(Cursor::new(v));
+        let map2: BTreeMap<i32, Vec<String>> = Deserialize::deserialize(&mut de).unwrap();
+        assert_eq!(map2, map);
+    }
+
+    #[test]
+    fn skip_struct() {
+        let mut v = Vec::new();
+        write_foo(&mut JsonProtocol::new(&mut v));
+        v.pop();
+        v.extend_from_slice(br#","next"]"#);
+
+        let mut de = JsonProtocol::new(Cursor::new(v));
+        assert!(de.read_message_begin().is_ok());
+        assert!(de.skip(ThriftType::Struct).is_ok());
+        assert_eq!(de.deserialize_str().unwrap(), "next");
+        assert!(de.read_message_end().is_ok());
+    }
+
+    #[test]
+    fn bad_version() {
+        let mut de = JsonProtocol::new(Cursor::new(br#"[2,"foo",1,0,{}]"#.to_vec()));
+        match de.read_message_begin() {
+            Err(Error::BadVersion) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn unexpected_char() {
+        let mut de = JsonProtocol::new(Cursor::new(br#"{"1":["i32":1}}"#.to_vec()));
+        assert!(de.read_struct_begin().is_ok());
+        match de.read_field_begin() {
+            Err(Error::InvalidJson(_)) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+}
@@ -1,7 +1,16 @@
 pub mod binary_protocol;
+pub mod compact_protocol;
+pub mod json_protocol;
+pub mod simple_json_protocol;
 pub use self::binary_protocol::BinaryProtocol;
+pub use self::compact_protocol::CompactProtocol;
+pub use self::json_protocol::JsonProtocol;
+pub use self::simple_json_protocol::SimpleJsonProtocol;
 
 use std::{io, convert, error, fmt};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+use std::ops::{Deref, DerefMut};
 use std::string::FromUtf8Error;
 
 #[derive(Debug)]
@@ -11,6 +20,21 @@ pub enum Error {
     Utf8Error(FromUtf8Error),
     BadVersion,
     ProtocolVersionMissing,
+    BadType(i8),
+    BadMessageType(i8),
+    NegativeSize(i32),
+    SizeLimit(usize),
+    ContainerSizeLimit(usize),
+    FrameSizeLimit(usize),
+    DepthLimit,
+    BadVarint,
+    /// A compact field header whose delta takes the field id past `i16::MAX`.
+    BadFieldId,
+    InvalidJson(String),
+    /// The named union was read with this many fields set instead of exactly one.
+    BadUnion(String, usize),
+    /// A `required` field was absent from the named struct.
+    MissingRequiredField { struct_: String, field: String },
 }
 
 impl fmt::Display for Error {
@@ -27,6 +51,18 @@ impl error::Error for Error {
             &Error::Utf8Error(_) => "internal error of utf8 conversion",
             &Error::BadVersion => "bad version",
             &Error::ProtocolVersionMissing => "protocol version missing",
+            &Error::BadType(_) => "bad type",
+            &Error::BadMessageType(_) => "bad message type",
+            &Error::NegativeSize(_) => "negative size",
+            &Error::SizeLimit(_) => "string or binary exceeds size limit",
+            &Error::ContainerSizeLimit(_) => "container exceeds size limit",
+            &Error::FrameSizeLimit(_) => "frame exceeds size limit",
+            &Error::DepthLimit => "struct nesting exceeds depth limit",
+            &Error::BadVarint => "bad varint",
+            &Error::BadFieldId => "field id out of range",
+            &Error::InvalidJson(_) => "invalid json",
+            &Error::BadUnion(_, _) => "union does not have exactly one field set",
+            &Error::MissingRequiredField { .. } => "required field is missing",
         }
     }
 
@@ -37,6 +73,18 @@ impl error::Error for Error {
             &Error::Utf8Error(ref e) => Some(e),
             &Error::BadVersion => None,
             &Error::ProtocolVersionMissing => None,
+            &Error::BadType(_) => None,
+            &Error::BadMessageType(_) => None,
+            &Error::NegativeSize(_) => None,
+            &Error::SizeLimit(_) => None,
+            &Error::ContainerSizeLimit(_) => None,
+            &Error::FrameSizeLimit(_) => None,
+            &Error::DepthLimit => None,
+            &Error::BadVarint => None,
+            &Error::BadFieldId => None,
+            &Error::InvalidJson(_) => None,
+            &Error::BadUnion(_, _) => None,
+            &Error::MissingRequiredField { .. } => None,
         }
     }
 }
@@ -50,7 +98,12 @@ impl convert::From<FromUtf8Error> for Error {
 
 impl convert::From<io::Error> for Error {
     fn from(err: io::Error) -> Error {
-        Error::Io(err)
+        // running out of bytes is reported as `EOF` so that callers can tell
+        // an incomplete message apart from a corrupt one
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => Error::EOF,
+            _ => Error::Io(err),
+        }
     }
 }
 
@@ -64,7 +117,64 @@ impl convert::From<Error> for io::Error {
 }
 
 
-#[derive(PartialEq, Eq, Debug)]
+/// Upper bounds on what a peer can make us allocate or recurse into.
+///
+/// Exceeding a limit fails decoding with the matching `Error` variant.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Limits {
+    /// Maximum size of a whole message, in bytes.
+    pub max_frame_size: usize,
+    /// Maximum length of a single string or binary value, in bytes.
+    pub max_string_size: usize,
+    /// Maximum number of elements in a list, set or map.
+    pub max_container_size: usize,
+    /// Maximum nesting of structs.
+    pub max_depth: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_frame_size: 16 * 1024 * 1024,
+            max_string_size: 16 * 1024 * 1024,
+            max_container_size: 1024 * 1024,
+            max_depth: 64,
+        }
+    }
+}
+
+impl Limits {
+    pub fn check_frame_size(&self, size: usize) -> Result<(), Error> {
+        if size > self.max_frame_size {
+            return Err(Error::FrameSizeLimit(size));
+        }
+        Ok(())
+    }
+
+    pub fn check_string_size(&self, size: usize) -> Result<(), Error> {
+        if size > self.max_string_size {
+            return Err(Error::SizeLimit(size));
+        }
+        Ok(())
+    }
+
+    pub fn check_container_size(&self, size: usize) -> Result<(), Error> {
+        if size > self.max_container_size {
+            return Err(Error::ContainerSizeLimit(size));
+        }
+        Ok(())
+    }
+
+    pub fn check_depth(&self, depth: usize) -> Result<(), Error> {
+        if depth > self.max_depth {
+            return Err(Error::DepthLimit);
+        }
+        Ok(())
+    }
+}
+
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum ThriftType {
     Stop = 0,
     Void = 1,
@@ -104,6 +214,16 @@ impl convert::From<i8> for ThriftType {
     }
 }
 
+impl ThriftType {
+    /// Like `From<i8>`, but returns `Error::BadType` for unknown values instead of panicking.
+    pub fn from_i8(val: i8) -> Result<ThriftType, Error> {
+        match val {
+            0 | 1 | 2 | 3 | 4 | 6 | 8 | 9 | 10 | 11 | 12 | 13 | 14 | 15 => Ok(ThriftType::from(val)),
+            e => Err(Error::BadType(e)),
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Debug)]
 pub enum ThriftMessageType {
     Call = 1,
@@ -124,6 +244,16 @@ impl convert::From<i8> for ThriftMessageType {
     }
 }
 
+impl ThriftMessageType {
+    /// Like `From<i8>`, but returns `Error::BadMessageType` for unknown values instead of panicking.
+    pub fn from_i8(val: i8) -> Result<ThriftMessageType, Error> {
+        match val {
+            1 | 2 | 3 | 4 => Ok(ThriftMessageType::from(val)),
+            e => Err(Error::BadMessageType(e)),
+        }
+    }
+}
+
 pub trait Serializer {
     fn serialize_bool(&mut self, val: bool) -> Result<(), Error>;
     fn serialize_str(&mut self, val: &str) -> Result<(), Error>;
@@ -157,16 +287,27 @@ pub trait Deserializer {
     fn deserialize_f64(&mut self) -> Result<f64, Error>;
     fn deserialize_bytes(&mut self) -> Result<Vec<u8>, Error>;
     fn deserialize_str(&mut self) -> Result<String, Error>;
+
+    /// Discards a string or binary value.
+    fn skip_bytes(&mut self) -> Result<(), Error> {
+        self.deserialize_bytes().map(|_| ())
+    }
 }
 
 pub trait Serialize {
     fn serialize<S>(&self, s: &mut S) -> Result<(), Error> where S: Serializer + ThriftSerializer;
+
+    /// The type written in field and container headers for this value.
+    fn thrift_type() -> ThriftType where Self: Sized {
+        ThriftType::Struct
+    }
 }
 
 pub trait ThriftSerializer {
     fn write_message_begin(&mut self,
                            _name: &str,
-                           _message_type: ThriftMessageType)
+                           _message_type: ThriftMessageType,
+                           _seq: i32)
                            -> Result<(), Error> {
         Ok(())
     }
@@ -191,16 +332,67 @@ pub trait ThriftSerializer {
         Ok(())
     }
 
+    fn write_list_begin(&mut self, _ty: ThriftType, _size: usize) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn write_list_end(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn write_set_begin(&mut self, _ty: ThriftType, _size: usize) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn write_set_end(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn write_map_begin(&mut self,
+                       _key_ty: ThriftType,
+                       _value_ty: ThriftType,
+                       _size: usize)
+                       -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn write_map_end(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
     fn write_message_end(&mut self) -> Result<(), Error> {
         Ok(())
     }
 }
 
+/// A call or reply that knows the sequence id of its message.
+///
+/// Implemented by the generated `*ServiceMethodArgs` and `*ServiceMethodReturn` enums.
+pub trait Sequenced {
+    fn seq(&self) -> i32;
+    /// Replaces the sequence id, as multiplexed clients do on the wire.
+    fn set_seq(&mut self, seq: i32);
+}
+
+/// A call or reply of a service which may have `oneway` methods.
+///
+/// Servers never answer oneway calls, so clients make up the reply themselves, and
+/// the transports drop the one a server produces instead of sending it.
+pub trait Oneway {
+    /// Whether this is a oneway call, or the reply standing in for one.
+    fn is_oneway(&self) -> bool;
+}
+
+/// A reply that can stand in for the one a oneway call never gets.
+pub trait OnewayReply: Oneway {
+    fn oneway_reply(seq: i32) -> Self;
+}
+
 #[derive(Debug)]
 pub struct ThriftMessage {
     pub name: String,
     pub ty: ThriftMessageType,
-    pub seq: i16,
+    pub seq: i32,
 }
 #[derive(Debug)]
 pub struct ThriftField {
@@ -208,6 +400,22 @@ pub struct ThriftField {
     pub ty: ThriftType,
     pub seq: i16,
 }
+#[derive(Debug)]
+pub struct ThriftList {
+    pub ty: ThriftType,
+    pub size: usize,
+}
+#[derive(Debug)]
+pub struct ThriftSet {
+    pub ty: ThriftType,
+    pub size: usize,
+}
+#[derive(Debug)]
+pub struct ThriftMap {
+    pub key_ty: ThriftType,
+    pub value_ty: ThriftType,
+    pub size: usize,
+}
 
 pub trait ThriftDeserializer {
     fn read_message_begin(&mut self) -> Result<ThriftMessage, Error>;
@@ -216,6 +424,81 @@ pub trait ThriftDeserializer {
     fn read_struct_end(&mut self) -> Result<(), Error>;
     fn read_field_begin(&mut self) -> Result<ThriftField, Error>;
     fn read_field_end(&mut self) -> Result<(), Error>;
+    fn read_list_begin(&mut self) -> Result<ThriftList, Error>;
+    fn read_list_end(&mut self) -> Result<(), Error>;
+    fn read_set_begin(&mut self) -> Result<ThriftSet, Error>;
+    fn read_set_end(&mut self) -> Result<(), Error>;
+    fn read_map_begin(&mut self) -> Result<ThriftMap, Error>;
+    fn read_map_end(&mut self) -> Result<(), Error>;
+
+    /// The limits values read are checked against.
+    fn limits(&self) -> &Limits;
+
+    /// Reads and discards a value of type `ty`, recursing into structs and containers.
+    ///
+    /// Used to step over fields that are unknown or have an unexpected type. Nesting
+    /// is bounded by `max_depth`, so that deep containers cannot exhaust the stack.
+    fn skip(&mut self, ty: ThriftType) -> Result<(), Error> where Self: Deserializer + Sized {
+        let depth = self.limits().max_depth;
+        skip_value(self, ty, depth)
+    }
+}
+
+// `depth` is the number of structs and containers that may still be entered.
+fn skip_value<D>(de: &mut D, ty: ThriftType, depth: usize) -> Result<(), Error>
+    where D: Deserializer + ThriftDeserializer
+{
+    match ty {
+        ThriftType::Struct | ThriftType::Map | ThriftType::Set | ThriftType::List if depth == 0 => {
+            return Err(Error::DepthLimit);
+        }
+        _ => (),
+    }
+    match ty {
+        ThriftType::Bool => try!(de.deserialize_bool().map(|_| ())),
+        ThriftType::Byte => try!(de.deserialize_i8().map(|_| ())),
+        ThriftType::I16 => try!(de.deserialize_i16().map(|_| ())),
+        ThriftType::I32 => try!(de.deserialize_i32().map(|_| ())),
+        ThriftType::U64 | ThriftType::I64 => try!(de.deserialize_i64().map(|_| ())),
+        ThriftType::Double => try!(de.deserialize_f64().map(|_| ())),
+        ThriftType::String => try!(de.skip_bytes()),
+        ThriftType::Struct => {
+            try!(de.read_struct_begin());
+            loop {
+                let field = try!(de.read_field_begin());
+                if field.ty == ThriftType::Stop {
+                    break;
+                }
+                try!(skip_value(de, field.ty, depth - 1));
+                try!(de.read_field_end());
+            }
+            try!(de.read_struct_end());
+        }
+        ThriftType::Map => {
+            let map = try!(de.read_map_begin());
+            for _ in 0..map.size {
+                try!(skip_value(de, map.key_ty, depth - 1));
+                try!(skip_value(de, map.value_ty, depth - 1));
+            }
+            try!(de.read_map_end());
+        }
+        ThriftType::Set => {
+            let set = try!(de.read_set_begin());
+            for _ in 0..set.size {
+                try!(skip_value(de, set.ty, depth - 1));
+            }
+            try!(de.read_set_end());
+        }
+        ThriftType::List => {
+            let list = try!(de.read_list_begin());
+            for _ in 0..list.size {
+                try!(skip_value(de, list.ty, depth - 1));
+            }
+            try!(de.read_list_end());
+        }
+        ty => return Err(Error::BadType(ty as i8)),
+    }
+    Ok(())
 }
 
 pub trait Deserialize: Sized {
@@ -328,12 +611,24 @@ impl Deserialize for String {
     }
 }
 
+impl Deserialize for () {
+    fn deserialize<D>(_de: &mut D) -> Result<Self, Error>
+        where D: Deserializer + ThriftDeserializer
+    {
+        Ok(())
+    }
+}
+
 impl Serialize for () {
     fn serialize<S>(&self, _s: &mut S) -> Result<(), Error>
         where S: Serializer + ThriftSerializer
     {
         Ok(())
     }
+
+    fn thrift_type() -> ThriftType {
+        ThriftType::Void
+    }
 }
 
 impl Serialize for bool {
@@ -342,6 +637,10 @@ impl Serialize for bool {
     {
         s.serialize_bool(*self)
     }
+
+    fn thrift_type() -> ThriftType {
+        ThriftType::Bool
+    }
 }
 
 impl<'a> Serialize for &'a str {
@@ -350,6 +649,10 @@ impl<'a> Serialize for &'a str {
     {
         s.serialize_str(self)
     }
+
+    fn thrift_type() -> ThriftType {
+        ThriftType::String
+    }
 }
 
 impl Serialize for String {
@@ -358,6 +661,10 @@ impl Serialize for String {
     {
         s.serialize_string(self.clone())
     }
+
+    fn thrift_type() -> ThriftType {
+        ThriftType::String
+    }
 }
 
 impl Serialize for usize {
@@ -366,6 +673,10 @@ impl Serialize for usize {
     {
         s.serialize_usize(*self)
     }
+
+    fn thrift_type() -> ThriftType {
+        ThriftType::I64
+    }
 }
 
 impl Serialize for isize {
@@ -374,6 +685,10 @@ impl Serialize for isize {
     {
         s.serialize_isize(*self)
     }
+
+    fn thrift_type() -> ThriftType {
+        ThriftType::I64
+    }
 }
 
 impl Serialize for u64 {
@@ -382,6 +697,10 @@ impl Serialize for u64 {
     {
         s.serialize_u64(*self)
     }
+
+    fn thrift_type() -> ThriftType {
+        ThriftType::I64
+    }
 }
 
 impl Serialize for i64 {
@@ -390,6 +709,10 @@ impl Serialize for i64 {
     {
         s.serialize_i64(*self)
     }
+
+    fn thrift_type() -> ThriftType {
+        ThriftType::I64
+    }
 }
 
 impl Serialize for i32 {
@@ -398,6 +721,10 @@ impl Serialize for i32 {
     {
         s.serialize_i32(*self)
     }
+
+    fn thrift_type() -> ThriftType {
+        ThriftType::I32
+    }
 }
 
 impl Serialize for u32 {
@@ -406,6 +733,10 @@ impl Serialize for u32 {
     {
         s.serialize_u32(*self)
     }
+
+    fn thrift_type() -> ThriftType {
+        ThriftType::I32
+    }
 }
 
 impl Serialize for u16 {
@@ -414,6 +745,10 @@ impl Serialize for u16 {
     {
         s.serialize_u16(*self)
     }
+
+    fn thrift_type() -> ThriftType {
+        ThriftType::I16
+    }
 }
 
 impl Serialize for i16 {
@@ -422,6 +757,10 @@ impl Serialize for i16 {
     {
         s.serialize_i16(*self)
     }
+
+    fn thrift_type() -> ThriftType {
+        ThriftType::I16
+    }
 }
 
 impl Serialize for i8 {
@@ -430,6 +769,10 @@ impl Serialize for i8 {
     {
         s.serialize_i8(*self)
     }
+
+    fn thrift_type() -> ThriftType {
+        ThriftType::Byte
+    }
 }
 
 impl Serialize for u8 {
@@ -438,6 +781,10 @@ impl Serialize for u8 {
     {
         s.serialize_u8(*self)
     }
+
+    fn thrift_type() -> ThriftType {
+        ThriftType::Byte
+    }
 }
 
 impl Serialize for f64 {
@@ -446,6 +793,10 @@ impl Serialize for f64 {
     {
         s.serialize_f64(*self)
     }
+
+    fn thrift_type() -> ThriftType {
+        ThriftType::Double
+    }
 }
 
 impl<'a> Serialize for &'a [u8] {
@@ -454,4 +805,261 @@ impl<'a> Serialize for &'a [u8] {
     {
         s.serialize_bytes(self)
     }
+
+    fn thrift_type() -> ThriftType {
+        ThriftType::String
+    }
+}
+
+/// A Thrift `binary`. Unlike a `Vec<u8>`, which is a `list<byte>`, it goes on the
+/// wire as a string of bytes.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Binary(pub Vec<u8>);
+
+impl Deref for Binary {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.0
+    }
+}
+
+impl DerefMut for Binary {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.0
+    }
+}
+
+impl From<Vec<u8>> for Binary {
+    fn from(bytes: Vec<u8>) -> Binary {
+        Binary(bytes)
+    }
+}
+
+impl Serialize for Binary {
+    fn serialize<S>(&self, s: &mut S) -> Result<(), Error>
+        where S: Serializer + ThriftSerializer
+    {
+        s.serialize_bytes(&self.0)
+    }
+
+    fn thrift_type() -> ThriftType {
+        ThriftType::String
+    }
+}
+
+impl Deserialize for Binary {
+    fn deserialize<D>(de: &mut D) -> Result<Self, Error>
+        where D: Deserializer + ThriftDeserializer
+    {
+        de.deserialize_bytes().map(Binary)
+    }
+}
+
+impl<T: Serialize> Serialize for Option<T> {
+    fn serialize<S>(&self, s: &mut S) -> Result<(), Error>
+        where S: Serializer + ThriftSerializer
+    {
+        match self {
+            &Some(ref val) => val.serialize(s),
+            &None => Ok(()),
+        }
+    }
+
+    fn thrift_type() -> ThriftType {
+        T::thrift_type()
+    }
+}
+
+impl<T: Serialize> Serialize for Vec<T> {
+    fn serialize<S>(&self, s: &mut S) -> Result<(), Error>
+        where S: Serializer + ThriftSerializer
+    {
+        try!(s.write_list_begin(T::thrift_type(), self.len()));
+        for val in self {
+            try!(val.serialize(s));
+        }
+        s.write_list_end()
+    }
+
+    fn thrift_type() -> ThriftType {
+        ThriftType::List
+    }
+}
+
+impl<T: Serialize> Serialize for VecDeque<T> {
+    fn serialize<S>(&self, s: &mut S) -> Result<(), Error>
+        where S: Serializer + ThriftSerializer
+    {
+        try!(s.write_list_begin(T::thrift_type(), self.len()));
+        for val in self {
+            try!(val.serialize(s));
+        }
+        s.write_list_end()
+    }
+
+    fn thrift_type() -> ThriftType {
+        ThriftType::List
+    }
+}
+
+impl<T: Serialize + Eq + Hash> Serialize for HashSet<T> {
+    fn serialize<S>(&self, s: &mut S) -> Result<(), Error>
+        where S: Serializer + ThriftSerializer
+    {
+        try!(s.write_set_begin(T::thrift_type(), self.len()));
+        for val in self {
+            try!(val.serialize(s));
+        }
+        s.write_set_end()
+    }
+
+    fn thrift_type() -> ThriftType {
+        ThriftType::Set
+    }
+}
+
+impl<T: Serialize + Ord> Serialize for BTreeSet<T> {
+    fn serialize<S>(&self, s: &mut S) -> Result<(), Error>
+        where S: Serializer + ThriftSerializer
+    {
+        try!(s.write_set_begin(T::thrift_type(), self.len()));
+        for val in self {
+            try!(val.serialize(s));
+        }
+        s.write_set_end()
+    }
+
+    fn thrift_type() -> ThriftType {
+        ThriftType::Set
+    }
+}
+
+impl<K: Serialize + Eq + Hash, V: Serialize> Serialize for HashMap<K, V> {
+    fn serialize<S>(&self, s: &mut S) -> Result<(), Error>
+        where S: Serializer + ThriftSerializer
+    {
+        try!(s.write_map_begin(K::thrift_type(), V::thrift_type(), self.len()));
+        for (key, val) in self {
+            try!(key.serialize(s));
+            try!(val.serialize(s));
+        }
+        s.write_map_end()
+    }
+
+    fn thrift_type() -> ThriftType {
+        ThriftType::Map
+    }
+}
+
+impl<K: Serialize + Ord, V: Serialize> Serialize for BTreeMap<K, V> {
+    fn serialize<S>(&self, s: &mut S) -> Result<(), Error>
+        where S: Serializer + ThriftSerializer
+    {
+        try!(s.write_map_begin(K::thrift_type(), V::thrift_type(), self.len()));
+        for (key, val) in self {
+            try!(key.serialize(s));
+            try!(val.serialize(s));
+        }
+        s.write_map_end()
+    }
+
+    fn thrift_type() -> ThriftType {
+        ThriftType::Map
+    }
+}
+
+impl<T: Deserialize> Deserialize for Option<T> {
+    fn deserialize<D>(de: &mut D) -> Result<Self, Error>
+        where D: Deserializer + ThriftDeserializer
+    {
+        Ok(Some(try!(T::deserialize(de))))
+    }
+}
+
+// sizes come from the peer, so capacity is not reserved up front
+impl<T: Deserialize> Deserialize for Vec<T> {
+    fn deserialize<D>(de: &mut D) -> Result<Self, Error>
+        where D: Deserializer + ThriftDeserializer
+    {
+        let list = try!(de.read_list_begin());
+        let mut ret = Vec::new();
+        for _ in 0..list.size {
+            ret.push(try!(T::deserialize(de)));
+        }
+        try!(de.read_list_end());
+        Ok(ret)
+    }
+}
+
+impl<T: Deserialize> Deserialize for VecDeque<T> {
+    fn deserialize<D>(de: &mut D) -> Result<Self, Error>
+        where D: Deserializer + ThriftDeserializer
+    {
+        let list = try!(de.read_list_begin());
+        let mut ret = VecDeque::new();
+        for _ in 0..list.size {
+            ret.push_back(try!(T::deserialize(de)));
+        }
+        try!(de.read_list_end());
+        Ok(ret)
+    }
+}
+
+impl<T: Deserialize + Eq + Hash> Deserialize for HashSet<T> {
+    fn deserialize<D>(de: &mut D) -> Result<Self, Error>
+        where D: Deserializer + ThriftDeserializer
+    {
+        let set = try!(de.read_set_begin());
+        let mut ret = HashSet::new();
+        for _ in 0..set.size {
+            ret.insert(try!(T::deserialize(de)));
+        }
+        try!(de.read_set_end());
+        Ok(ret)
+    }
+}
+
+impl<T: Deserialize + Ord> Deserialize for BTreeSet<T> {
+    fn deserialize<D>(de: &mut D) -> Result<Self, Error>
+        where D: Deserializer + ThriftDeserializer
+    {
+        let set = try!(de.read_set_begin());
+        let mut ret = BTreeSet::new();
+        for _ in 0..set.size {
+            ret.insert(try!(T::deserialize(de)));
+        }
+        try!(de.read_set_end());
+        Ok(ret)
+    }
+}
+
+impl<K: Deserialize + Eq + Hash, V: Deserialize> Deserialize for HashMap<K, V> {
+    fn deserialize<D>(de: &mut D) -> Result<Self, Error>
+        where D: Deserializer + ThriftDeserializer
+    {
+        let map = try!(de.read_map_begin());
+        let mut ret = HashMap::new();
+        for _ in 0..map.size {
+            let key = try!(K::deserialize(de));
+            ret.insert(key, try!(V::deserialize(de)));
+        }
+        try!(de.read_map_end());
+        Ok(ret)
+    }
+}
+
+impl<K: Deserialize + Ord, V: Deserialize> Deserialize for BTreeMap<K, V> {
+    fn deserialize<D>(de: &mut D) -> Result<Self, Error>
+        where D: Deserializer + ThriftDeserializer
+    {
+        let map = try!(de.read_map_begin());
+        let mut ret = BTreeMap::new();
+        for _ in 0..map.size {
+            let key = try!(K::deserialize(de));
+            ret.insert(key, try!(V::deserialize(de)));
+        }
+        try!(de.read_map_end());
+        Ok(ret)
+    }
 }
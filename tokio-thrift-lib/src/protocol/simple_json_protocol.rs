@@ -0,0 +1,314 @@
+use super::{Serialize, Serializer, ThriftSerializer, ThriftMessageType, ThriftType, Error};
+use super::json_protocol::{Context, BASE64_CONFIG, write_escaped, special_double};
+use transport::{VoidTransport, WriteTransport};
+use byteorder::WriteBytesExt;
+use rustc_serialize::base64::ToBase64;
+
+/// Write-only JSON protocol keyed by field names, like Apache's TSimpleJSONProtocol.
+///
+/// The output is meant for humans (logging, debugging) and cannot be read back,
+/// since field ids and types are dropped.
+pub struct SimpleJsonProtocol<T> {
+    inner: T,
+    contexts: Vec<Context>,
+}
+
+/// Dumps any `Serialize` value as a JSON string.
+///
+/// ```
+/// use tokio_thrift::protocol::simple_json_protocol;
+///
+/// assert_eq!(simple_json_protocol::to_string(&"hello".to_string()).unwrap(), "\"hello\"");
+/// ```
+pub fn to_string<S: Serialize>(val: &S) -> Result<String, Error> {
+    let mut buf = Vec::new();
+    try!(val.serialize(&mut SimpleJsonProtocol::new(&mut buf)));
+    Ok(try!(String::from_utf8(buf)))
+}
+
+impl <T>SimpleJsonProtocol<T> {
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn context(&mut self) -> &mut Context {
+        self.contexts.last_mut().expect("tokio-thrift internal error: empty json context")
+    }
+
+    fn pop_context(&mut self) {
+        if self.contexts.len() > 1 {
+            self.contexts.pop();
+        }
+    }
+}
+
+impl <T: VoidTransport>SimpleJsonProtocol<T> {
+    pub fn new(inner: T) -> Self {
+        SimpleJsonProtocol {
+            inner: inner,
+            contexts: vec![Context::Base],
+        }
+    }
+}
+
+impl <T: VoidTransport>From<T> for SimpleJsonProtocol<T> {
+    fn from(w: T) -> Self {
+        Self::new(w)
+    }
+}
+
+impl <T: WriteTransport>SimpleJsonProtocol<T> {
+    fn write_context(&mut self) -> Result<(), Error> {
+        if let Some(sep) = self.context().separator() {
+            try!(self.inner.write_u8(sep));
+        }
+        Ok(())
+    }
+
+    // non-string values used as object keys must be quoted
+    fn write_plain(&mut self, val: &str) -> Result<(), Error> {
+        try!(self.write_context());
+        if self.context().escape_num() {
+            try!(write!(self.inner, "\"{}\"", val));
+        } else {
+            try!(write!(self.inner, "{}", val));
+        }
+        Ok(())
+    }
+
+    fn write_json_string(&mut self, val: &[u8]) -> Result<(), Error> {
+        try!(self.write_context());
+        try!(write_escaped(&mut self.inner, val));
+        Ok(())
+    }
+
+    fn write_json_object_start(&mut self) -> Result<(), Error> {
+        try!(self.write_context());
+        try!(self.inner.write_u8(b'{'));
+        self.contexts.push(Context::Pair { first: true, colon: true });
+        Ok(())
+    }
+
+    fn write_json_object_end(&mut self) -> Result<(), Error> {
+        self.pop_context();
+        try!(self.inner.write_u8(b'}'));
+        Ok(())
+    }
+
+    fn write_json_array_start(&mut self) -> Result<(), Error> {
+        try!(self.write_context());
+        try!(self.inner.write_u8(b'['));
+        self.contexts.push(Context::List { first: true });
+        Ok(())
+    }
+
+    fn write_json_array_end(&mut self) -> Result<(), Error> {
+        self.pop_context();
+        try!(self.inner.write_u8(b']'));
+        Ok(())
+    }
+}
+
+impl <T: WriteTransport> Serializer for SimpleJsonProtocol<T> {
+
+    fn serialize_bool(&mut self, val: bool) -> Result<(), Error> {
+        self.write_plain(if val { "true" } else { "false" })
+    }
+
+    fn serialize_usize(&mut self, val: usize) -> Result<(), Error> {
+        self.write_plain(&val.to_string())
+    }
+
+    fn serialize_isize(&mut self, val: isize) -> Result<(), Error> {
+        self.write_plain(&val.to_string())
+    }
+
+    fn serialize_u64(&mut self, val: u64) -> Result<(), Error> {
+        self.write_plain(&val.to_string())
+    }
+
+    fn serialize_i64(&mut self, val: i64) -> Result<(), Error> {
+        self.write_plain(&val.to_string())
+    }
+
+    fn serialize_u32(&mut self, val: u32) -> Result<(), Error> {
+        self.write_plain(&val.to_string())
+    }
+
+    fn serialize_i32(&mut self, val: i32) -> Result<(), Error> {
+        self.write_plain(&val.to_string())
+    }
+
+    fn serialize_u16(&mut self, val: u16) -> Result<(), Error> {
+        self.write_plain(&val.to_string())
+    }
+
+    fn serialize_i16(&mut self, val: i16) -> Result<(), Error> {
+        self.write_plain(&val.to_string())
+    }
+
+    fn serialize_u8(&mut self, val: u8) -> Result<(), Error> {
+        self.write_plain(&val.to_string())
+    }
+
+    fn serialize_i8(&mut self, val: i8) -> Result<(), Error> {
+        self.write_plain(&val.to_string())
+    }
+
+    fn serialize_f64(&mut self, val: f64) -> Result<(), Error> {
+        match special_double(val) {
+            Some(s) => self.write_json_string(s.as_bytes()),
+            None => self.write_plain(&val.to_string()),
+        }
+    }
+
+    fn serialize_bytes(&mut self, val: &[u8]) -> Result<(), Error> {
+        let encoded = val.to_base64(BASE64_CONFIG);
+        self.write_json_string(encoded.as_bytes())
+    }
+
+    fn serialize_str(&mut self, val: &str) -> Result<(), Error> {
+        self.write_json_string(val.as_bytes())
+    }
+
+    fn serialize_string(&mut self, val: String) -> Result<(), Error> {
+        self.serialize_str(&*val)
+    }
+}
+
+impl <T: WriteTransport>ThriftSerializer for SimpleJsonProtocol<T> {
+    fn write_message_begin(&mut self, name: &str, message_type: ThriftMessageType, seq: i32) -> Result<(), Error> {
+        try!(self.write_json_array_start());
+        try!(self.write_json_string(name.as_bytes()));
+        try!(self.serialize_i8(message_type as i8));
+        try!(self.serialize_i32(seq));
+        Ok(())
+    }
+
+    fn write_struct_begin(&mut self, _name: &str) -> Result<(), Error> {
+        self.write_json_object_start()
+    }
+
+    fn write_struct_end(&mut self) -> Result<(), Error> {
+        self.write_json_object_end()
+    }
+
+    fn write_field_begin(&mut self, name: &str, _ty: ThriftType, _id: i16) -> Result<(), Error> {
+        self.write_json_string(name.as_bytes())
+    }
+
+    fn write_field_end(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn write_field_stop(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn write_list_begin(&mut self, _ty: ThriftType, _size: usize) -> Result<(), Error> {
+        self.write_json_array_start()
+    }
+
+    fn write_list_end(&mut self) -> Result<(), Error> {
+        self.write_json_array_end()
+    }
+
+    fn write_set_begin(&mut self, _ty: ThriftType, _size: usize) -> Result<(), Error> {
+        self.write_json_array_start()
+    }
+
+    fn write_set_end(&mut self) -> Result<(), Error> {
+        self.write_json_array_end()
+    }
+
+    fn write_map_begin(&mut self, _key_ty: ThriftType, _value_ty: ThriftType, _size: usize) -> Result<(), Error> {
+        self.write_json_object_start()
+    }
+
+    fn write_map_end(&mut self) -> Result<(), Error> {
+        self.write_json_object_end()
+    }
+
+    fn write_message_end(&mut self) -> Result<(), Error> {
+        self.write_json_array_end()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use protocol::{ThriftMessageType, ThriftType, ThriftSerializer, Serialize, Serializer, Error};
+    use super::*;
+
+    struct Inner {
+        flag: bool,
+        ratio: f64,
+    }
+
+    struct Outer {
+        name: String,
+        inner: Inner,
+    }
+
+    impl Serialize for Inner {
+        fn serialize<S>(&self, s: &mut S) -> Result<(), Error>
+            where S: Serializer + ThriftSerializer
+        {
+            s.write_struct_begin("Inner")?;
+            s.write_field_begin("flag", ThriftType::Bool, 1)?;
+            self.flag.serialize(s)?;
+            s.write_field_end()?;
+            s.write_field_begin("ratio", ThriftType::Double, 2)?;
+            self.ratio.serialize(s)?;
+            s.write_field_end()?;
+            s.write_field_stop()?;
+            s.write_struct_end()?;
+            Ok(())
+        }
+    }
+
+    impl Serialize for Outer {
+        fn serialize<S>(&self, s: &mut S) -> Result<(), Error>
+            where S: Serializer + ThriftSerializer
+        {
+            s.write_message_begin("dump", ThriftMessageType::Call, 5)?;
+            s.write_struct_begin("Outer")?;
+            s.write_field_begin("name", ThriftType::String, 1)?;
+            self.name.serialize(s)?;
+            s.write_field_end()?;
+            s.write_field_begin("inner", ThriftType::Struct, 2)?;
+            self.inner.serialize(s)?;
+            s.write_field_end()?;
+            s.write_field_stop()?;
+            s.write_struct_end()?;
+            s.write_message_end()?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn nested_struct() {
+        let val = Outer {
+            name: "a\tb".to_string(),
+            inner: Inner { flag: true, ratio: ::std::f64::NAN },
+        };
+
+        assert_eq!(to_string(&val).unwrap(),
+                   r#"["dump",1,5,{"name":"a\tb","inner":{"flag":true,"ratio":"NaN"}}]"#);
+    }
+
+    #[test]
+    fn bytes_as_base64() {
+        let bytes: &[u8] = b"thrift";
+        assert_eq!(to_string(&bytes).unwrap(), "\"dGhyaWZ0\"");
+    }
+
+    #[test]
+    fn containers() {
+        let mut map = BTreeMap::new();
+        map.insert(1i32, vec![true]);
+        map.insert(2i32, vec![]);
+        assert_eq!(to_string(&map).unwrap(), r#"{"1":[true],"2":[]}"#);
+    }
+}
@@ -1,18 +1,106 @@
+use std::collections::{HashMap, VecDeque};
 use std::marker::PhantomData;
+use futures::{task, Async, AsyncSink, Poll, Sink, StartSend, Stream};
 use tokio_core::io::{Codec, EasyBuf, Io, Framed};
 use tokio_proto::pipeline::{ServerProto, ClientProto, Pipeline};
+use tokio_proto::multiplex::{self, Multiplex, RequestId};
 use tokio_proto::{TcpServer, TcpClient};
 use std::io;
-use protocol::{Error, BinaryProtocol};
-use protocol::{Deserialize, Serialize};
+use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
+use protocol::{Error, BinaryProtocol, Limits};
+use protocol::{Deserialize, Serialize, Sequenced, Oneway, OnewayReply};
 use std::net::SocketAddr;
 
 
-pub struct ThriftCodec<In, Out>(PhantomData<In>, PhantomData<Out>);
+/// Size of the big-endian `i32` length prefix in front of every frame.
+const FRAME_HEADER_SIZE: usize = 4;
+
+/// How messages are delimited on the wire.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TransportMode {
+    /// Every message is preceded by its size as a big-endian `i32` (`TFramedTransport`).
+    Framed,
+    /// Messages are written back to back with no delimiter (`TBufferedTransport`).
+    Buffered,
+}
+
+impl Default for TransportMode {
+    fn default() -> Self {
+        TransportMode::Framed
+    }
+}
+
+/// Codec for Thrift messages over binary protocol.
+///
+/// In framed mode decoding waits until a whole frame has arrived before handing it
+/// to the protocol. In buffered mode the message is parsed straight from the read
+/// buffer, and running out of bytes means more have to be read, while any other
+/// protocol error means the stream is corrupt.
+///
+/// Messages exceeding `Limits` fail decoding, which closes the connection. Messages
+/// serializing to nothing, like the replies standing in for oneway calls, are not sent.
+pub struct ThriftCodec<In, Out> {
+    mode: TransportMode,
+    limits: Limits,
+    _marker: PhantomData<(In, Out)>,
+}
 
 impl<In, Out> ThriftCodec<In, Out> {
     pub fn new() -> Self {
-        ThriftCodec(PhantomData, PhantomData)
+        Self::with_mode(TransportMode::default())
+    }
+
+    pub fn with_mode(mode: TransportMode) -> Self {
+        Self::with_limits(mode, Limits::default())
+    }
+
+    pub fn with_limits(mode: TransportMode, limits: Limits) -> Self {
+        ThriftCodec {
+            mode: mode,
+            limits: limits,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<In: Deserialize, Out: Serialize> ThriftCodec<In, Out> {
+    fn decode_framed(&mut self, buf: &mut EasyBuf) -> Result<Option<In>, io::Error> {
+        if buf.len() < FRAME_HEADER_SIZE {
+            return Ok(None);
+        }
+        let size = BigEndian::read_i32(&buf.as_slice()[..FRAME_HEADER_SIZE]);
+        if size < 0 {
+            return Err(io::Error::from(Error::NegativeSize(size)));
+        }
+        let size = size as usize;
+        try!(self.limits.check_frame_size(size));
+        if buf.len() < FRAME_HEADER_SIZE + size {
+            return Ok(None);
+        }
+        buf.drain_to(FRAME_HEADER_SIZE);
+        let frame = buf.drain_to(size);
+
+        // the frame is complete, so running out of bytes here means it is corrupt
+        let mut protocol = BinaryProtocol::with_limits(io::Cursor::new(frame.as_slice()), self.limits);
+        let ret = try!(In::deserialize(&mut protocol));
+        Ok(Some(ret))
+    }
+
+    fn decode_buffered(&mut self, buf: &mut EasyBuf) -> Result<Option<In>, io::Error> {
+        let (ret, size) = {
+            let mut protocol = BinaryProtocol::with_limits(io::Cursor::new(buf.as_slice()), self.limits);
+            let ret = match In::deserialize(&mut protocol) {
+                Ok(ret) => ret,
+                Err(Error::EOF) => {
+                    try!(self.limits.check_frame_size(buf.len()));
+                    return Ok(None);
+                }
+                Err(e) => return Err(io::Error::from(e)),
+            };
+            (ret, protocol.into_inner().position())
+        };
+        buf.drain_to(size as usize);
+        Ok(Some(ret))
     }
 }
 
@@ -21,47 +109,191 @@ impl<In: Deserialize, Out: Serialize> Codec for ThriftCodec<In, Out> {
     type Out = Out;
 
     fn decode(&mut self, buf: &mut EasyBuf) -> Result<Option<Self::In>, io::Error> {
-        let cur = io::Cursor::new(buf);
-        let mut protocol = BinaryProtocol::from(cur);
-        let ret = match Self::In::deserialize(&mut protocol) {
-            Ok(ret) => ret,
-            Err(Error::EOF) => return Ok(None),
-            Err(e) => return Err(io::Error::from(e)),
-        };
-        let cur = protocol.into_inner();
-        let size = cur.position();
-        let buf = cur.into_inner();
-        buf.drain_to(size as usize);
-        Ok(Some(ret))
+        match self.mode {
+            TransportMode::Framed => self.decode_framed(buf),
+            TransportMode::Buffered => self.decode_buffered(buf),
+        }
     }
 
     fn encode(&mut self, msg: Self::Out, buf: &mut Vec<u8>) -> io::Result<()> {
-        let mut protocol = BinaryProtocol::from(buf);
-        msg.serialize(&mut protocol)
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        if self.mode == TransportMode::Buffered {
+            return msg.serialize(&mut BinaryProtocol::from(buf))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+        }
+
+        let mut frame = Vec::new();
+        try!(msg.serialize(&mut BinaryProtocol::from(&mut frame))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)));
+        if frame.is_empty() {
+            return Ok(());
+        }
+        if frame.len() > i32::max_value() as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("frame too large: {} bytes", frame.len())));
+        }
+        try!(buf.write_i32::<BigEndian>(frame.len() as i32));
+        buf.extend_from_slice(&frame);
+        Ok(())
+    }
+}
+
+
+/// Client transport answering oneway calls itself, since servers never reply to them.
+///
+/// On a pipelined connection the made-up reply is queued behind the replies to the
+/// calls sent before, so that replies still come in the order of the calls. On a
+/// multiplexed connection it is handed out right away.
+pub struct OnewayTransport<T: Stream, P> {
+    inner: T,
+    replies: VecDeque<Option<T::Item>>,
+    _marker: PhantomData<P>,
+}
+
+impl<T: Stream, P> OnewayTransport<T, P> {
+    pub fn new(inner: T) -> Self {
+        OnewayTransport {
+            inner: inner,
+            replies: VecDeque::new(),
+            _marker: PhantomData,
+        }
+    }
 
+    fn push_reply(&mut self, reply: Option<T::Item>) {
+        if reply.is_some() {
+            // the dispatcher has to come back for it, as no read will wake it up
+            task::park().unpark();
+        }
+        self.replies.push_back(reply);
     }
 }
 
+impl<T, Res> Stream for OnewayTransport<T, Pipeline>
+    where T: Stream<Item = Res, Error = io::Error>
+{
+    type Item = Res;
+    type Error = io::Error;
 
-pub struct ThriftProto<Req, Res>(PhantomData<Req>, PhantomData<Res>);
+    fn poll(&mut self) -> Poll<Option<Res>, io::Error> {
+        if let Some(&Some(_)) = self.replies.front() {
+            return Ok(Async::Ready(self.replies.pop_front().and_then(|reply| reply)));
+        }
+        let reply = match try!(self.inner.poll()) {
+            Async::Ready(reply) => reply,
+            Async::NotReady => return Ok(Async::NotReady),
+        };
+        self.replies.pop_front();
+        Ok(Async::Ready(reply))
+    }
+}
+
+impl<T, Req, Res> Sink for OnewayTransport<T, Pipeline>
+    where T: Stream<Item = Res, Error = io::Error> + Sink<SinkItem = Req, SinkError = io::Error>,
+          Req: Oneway + Sequenced,
+          Res: OnewayReply
+{
+    type SinkItem = Req;
+    type SinkError = io::Error;
+
+    fn start_send(&mut self, req: Req) -> StartSend<Req, io::Error> {
+        let reply = if req.is_oneway() {
+            Some(Res::oneway_reply(req.seq()))
+        } else {
+            None
+        };
+        if let AsyncSink::NotReady(req) = try!(self.inner.start_send(req)) {
+            return Ok(AsyncSink::NotReady(req));
+        }
+        self.push_reply(reply);
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), io::Error> {
+        self.inner.poll_complete()
+    }
+}
+
+impl<T, Res> Stream for OnewayTransport<T, Multiplex>
+    where T: Stream<Item = (RequestId, Res), Error = io::Error>
+{
+    type Item = (RequestId, Res);
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<(RequestId, Res)>, io::Error> {
+        if let Some(reply) = self.replies.pop_front() {
+            return Ok(Async::Ready(reply));
+        }
+        self.inner.poll()
+    }
+}
+
+impl<T, Req, Res> Sink for OnewayTransport<T, Multiplex>
+    where T: Stream<Item = (RequestId, Res), Error = io::Error>
+              + Sink<SinkItem = (RequestId, Req), SinkError = io::Error>,
+          Req: Oneway + Sequenced,
+          Res: OnewayReply
+{
+    type SinkItem = (RequestId, Req);
+    type SinkError = io::Error;
+
+    fn start_send(&mut self, req: (RequestId, Req)) -> StartSend<(RequestId, Req), io::Error> {
+        let reply = if req.1.is_oneway() {
+            Some((req.0, Res::oneway_reply(req.1.seq())))
+        } else {
+            None
+        };
+        if let AsyncSink::NotReady(req) = try!(self.inner.start_send(req)) {
+            return Ok(AsyncSink::NotReady(req));
+        }
+        if reply.is_some() {
+            self.push_reply(reply);
+        }
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), io::Error> {
+        self.inner.poll_complete()
+    }
+}
+
+
+pub struct ThriftProto<Req, Res> {
+    mode: TransportMode,
+    limits: Limits,
+    _marker: PhantomData<(Req, Res)>,
+}
 
 impl<Req, Res> ThriftProto<Req, Res> {
     pub fn new() -> Self {
-        ThriftProto(PhantomData, PhantomData)
+        Self::with_mode(TransportMode::default())
+    }
+
+    pub fn with_mode(mode: TransportMode) -> Self {
+        Self::with_limits(mode, Limits::default())
+    }
+
+    pub fn with_limits(mode: TransportMode, limits: Limits) -> Self {
+        ThriftProto {
+            mode: mode,
+            limits: limits,
+            _marker: PhantomData,
+        }
     }
 }
 
 
-impl<Req: Serialize + 'static, Res: Deserialize + 'static, T: Io + 'static> ClientProto<T>
-    for ThriftProto<Req, Res> {
+impl<Req, Res, T> ClientProto<T> for ThriftProto<Req, Res>
+    where Req: Serialize + Sequenced + Oneway + 'static,
+          Res: Deserialize + OnewayReply + 'static,
+          T: Io + 'static
+{
     type Request = Req;
     type Response = Res;
-    type Transport = Framed<T, ThriftCodec<Res, Req>>;
+    type Transport = OnewayTransport<Framed<T, ThriftCodec<Res, Req>>, Pipeline>;
     type BindTransport = Result<Self::Transport, io::Error>;
 
     fn bind_transport(&self, io: T) -> Self::BindTransport {
-        Ok(io.framed(ThriftCodec::<Res, Req>::new()))
+        let framed = io.framed(ThriftCodec::<Res, Req>::with_limits(self.mode, self.limits));
+        Ok(OnewayTransport::new(framed))
     }
 }
 
@@ -73,12 +305,148 @@ impl<Req: Deserialize + 'static, Res: Serialize + 'static, T: Io + 'static> Serv
     type BindTransport = Result<Self::Transport, io::Error>;
 
     fn bind_transport(&self, io: T) -> Self::BindTransport {
-        Ok(io.framed(ThriftCodec::new()))
+        Ok(io.framed(ThriftCodec::with_limits(self.mode, self.limits)))
     }
 }
 
 
-pub fn new_tcp_client<Req: Serialize + 'static, Res: Deserialize + 'static>
+/// Codec for multiplexed connections, where replies may arrive in any order.
+///
+/// tokio-proto matches replies to requests by `RequestId`, so the codec maps those
+/// to the seqids of the messages. The client side sends every call with its request
+/// id as seqid, which is unique on the connection whatever seqids the callers chose,
+/// and puts the caller's seqid back on the reply. It fails on a reply it is not
+/// waiting for. Oneway calls keep their seqid and are never in flight, as no reply
+/// will come for them. The server side numbers calls itself, so that peers reusing
+/// seqids are answered all the same.
+pub struct ThriftMultiplexCodec<In, Out> {
+    inner: ThriftCodec<In, Out>,
+    client: bool,
+    // the request id and the caller's seqid of each call, by seqid on the wire
+    in_flight: HashMap<i32, (RequestId, i32)>,
+    next_id: RequestId,
+}
+
+impl<In, Out> ThriftMultiplexCodec<In, Out> {
+    pub fn client(mode: TransportMode, limits: Limits) -> Self {
+        Self::with_side(true, mode, limits)
+    }
+
+    pub fn server(mode: TransportMode, limits: Limits) -> Self {
+        Self::with_side(false, mode, limits)
+    }
+
+    fn with_side(client: bool, mode: TransportMode, limits: Limits) -> Self {
+        ThriftMultiplexCodec {
+            inner: ThriftCodec::with_limits(mode, limits),
+            client: client,
+            in_flight: HashMap::new(),
+            next_id: 0,
+        }
+    }
+}
+
+impl<In, Out> Codec for ThriftMultiplexCodec<In, Out>
+    where In: Deserialize + Sequenced,
+          Out: Serialize + Sequenced + Oneway
+{
+    type In = (RequestId, In);
+    type Out = (RequestId, Out);
+
+    fn decode(&mut self, buf: &mut EasyBuf) -> Result<Option<Self::In>, io::Error> {
+        let mut msg = match try!(self.inner.decode(buf)) {
+            Some(msg) => msg,
+            None => return Ok(None),
+        };
+        let id = if self.client {
+            match self.in_flight.remove(&msg.seq()) {
+                Some((id, seq)) => {
+                    msg.set_seq(seq);
+                    id
+                }
+                None => {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                              format!("reply to unknown seqid {}", msg.seq())))
+                }
+            }
+        } else {
+            let id = self.next_id;
+            self.next_id = self.next_id.wrapping_add(1);
+            id
+        };
+        Ok(Some((id, msg)))
+    }
+
+    fn encode(&mut self, msg: Self::Out, buf: &mut Vec<u8>) -> io::Result<()> {
+        let (id, mut msg) = msg;
+        if self.client && !msg.is_oneway() {
+            let wire = id as i32;
+            self.in_flight.insert(wire, (id, msg.seq()));
+            msg.set_seq(wire);
+        }
+        self.inner.encode(msg, buf)
+    }
+}
+
+
+/// Like `ThriftProto`, but lets the server answer calls out of order.
+pub struct ThriftMultiplexProto<Req, Res> {
+    mode: TransportMode,
+    limits: Limits,
+    _marker: PhantomData<(Req, Res)>,
+}
+
+impl<Req, Res> ThriftMultiplexProto<Req, Res> {
+    pub fn new() -> Self {
+        Self::with_mode(TransportMode::default())
+    }
+
+    pub fn with_mode(mode: TransportMode) -> Self {
+        Self::with_limits(mode, Limits::default())
+    }
+
+    pub fn with_limits(mode: TransportMode, limits: Limits) -> Self {
+        ThriftMultiplexProto {
+            mode: mode,
+            limits: limits,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<Req, Res, T> multiplex::ClientProto<T> for ThriftMultiplexProto<Req, Res>
+    where Req: Serialize + Sequenced + Oneway + 'static,
+          Res: Deserialize + Sequenced + OnewayReply + 'static,
+          T: Io + 'static
+{
+    type Request = Req;
+    type Response = Res;
+    type Transport = OnewayTransport<Framed<T, ThriftMultiplexCodec<Res, Req>>, Multiplex>;
+    type BindTransport = Result<Self::Transport, io::Error>;
+
+    fn bind_transport(&self, io: T) -> Self::BindTransport {
+        Ok(OnewayTransport::new(io.framed(ThriftMultiplexCodec::client(self.mode, self.limits))))
+    }
+}
+
+impl<Req, Res, T> multiplex::ServerProto<T> for ThriftMultiplexProto<Req, Res>
+    where Req: Deserialize + Sequenced + 'static,
+          Res: Serialize + Sequenced + Oneway + 'static,
+          T: Io + 'static
+{
+    type Request = Req;
+    type Response = Res;
+    type Transport = Framed<T, ThriftMultiplexCodec<Req, Res>>;
+    type BindTransport = Result<Self::Transport, io::Error>;
+
+    fn bind_transport(&self, io: T) -> Self::BindTransport {
+        Ok(io.framed(ThriftMultiplexCodec::server(self.mode, self.limits)))
+    }
+}
+
+
+pub fn new_tcp_client<Req: Serialize + Sequenced + Oneway + 'static,
+                      Res: Deserialize + OnewayReply + 'static>
     ()
     -> TcpClient<Pipeline, ThriftProto<Req, Res>>
 {
@@ -91,3 +459,261 @@ pub fn new_tcp_server<Req: Deserialize + Send + Sync + 'static,
      -> TcpServer<Pipeline, ThriftProto<Req, Res>> {
     TcpServer::new(ThriftProto::<Req, Res>::new(), addr)
 }
+
+pub fn new_multiplex_tcp_client<Req: Serialize + Sequenced + Oneway + 'static,
+                                Res: Deserialize + Sequenced + OnewayReply + 'static>
+    ()
+    -> TcpClient<Multiplex, ThriftMultiplexProto<Req, Res>>
+{
+    TcpClient::new(ThriftMultiplexProto::<Req, Res>::new())
+}
+
+pub fn new_multiplex_tcp_server<Req: Deserialize + Sequenced + Send + Sync + 'static,
+                                Res: Serialize + Sequenced + Oneway + Send + Sync + 'static>
+    (addr: SocketAddr)
+     -> TcpServer<Multiplex, ThriftMultiplexProto<Req, Res>> {
+    TcpServer::new(ThriftMultiplexProto::<Req, Res>::new(), addr)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use futures::{future, Async, AsyncSink, Future, Poll, Sink, StartSend, Stream};
+    use tokio_core::io::{Codec, EasyBuf};
+    use protocol::{Serializer, ThriftSerializer, Deserializer, ThriftDeserializer};
+    use super::*;
+
+    #[test]
+    fn encode_length_prefix() {
+        let mut codec = ThriftCodec::<String, String>::new();
+        let mut buf = Vec::new();
+        codec.encode("foo".to_string(), &mut buf).unwrap();
+        assert_eq!(buf, [0, 0, 0, 7, 0, 0, 0, 3, b'f', b'o', b'o']);
+    }
+
+    #[test]
+    fn decode_waits_for_whole_frame() {
+        let mut codec = ThriftCodec::<String, String>::new();
+        let bytes = [0, 0, 0, 7, 0, 0, 0, 3, b'f', b'o', b'o', 0, 0];
+        let mut buf = EasyBuf::new();
+
+        buf.get_mut().extend_from_slice(&bytes[..2]);
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+        buf.get_mut().extend_from_slice(&bytes[2..9]);
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+        assert_eq!(buf.len(), 9);
+
+        buf.get_mut().extend_from_slice(&bytes[9..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some("foo".to_string()));
+        assert_eq!(buf.as_slice(), [0, 0]);
+    }
+
+    #[test]
+    fn decode_truncated_frame_is_error() {
+        let mut codec = ThriftCodec::<String, String>::new();
+        let mut buf = EasyBuf::new();
+        buf.get_mut().extend_from_slice(&[0, 0, 0, 2, 0, 0]);
+        assert!(codec.decode(&mut buf).is_err());
+    }
+
+    #[test]
+    fn decode_frame_over_limit() {
+        let limits = Limits { max_frame_size: 8, ..Limits::default() };
+        let mut codec = ThriftCodec::<String, String>::with_limits(TransportMode::Framed, limits);
+        let mut buf = EasyBuf::new();
+        buf.get_mut().extend_from_slice(&[0, 0, 0, 9]);
+        assert!(codec.decode(&mut buf).is_err());
+    }
+
+    #[test]
+    fn empty_message_is_not_sent() {
+        let mut codec = ThriftCodec::<(), ()>::new();
+        let mut buf = Vec::new();
+        codec.encode((), &mut buf).unwrap();
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn buffered_round_trip() {
+        let mut codec = ThriftCodec::<String, String>::with_mode(TransportMode::Buffered);
+        let mut bytes = Vec::new();
+        codec.encode("foo".to_string(), &mut bytes).unwrap();
+        assert_eq!(bytes, [0, 0, 0, 3, b'f', b'o', b'o']);
+
+        let mut buf = EasyBuf::new();
+        buf.get_mut().extend_from_slice(&bytes[..5]);
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+        assert_eq!(buf.len(), 5);
+
+        buf.get_mut().extend_from_slice(&bytes[5..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some("foo".to_string()));
+        assert_eq!(buf.len(), 0);
+    }
+
+    #[test]
+    fn buffered_message_over_limit() {
+        let limits = Limits { max_frame_size: 8, ..Limits::default() };
+        let mut codec = ThriftCodec::<String, String>::with_limits(TransportMode::Buffered, limits);
+        let mut buf = EasyBuf::new();
+        buf.get_mut().extend_from_slice(&[0, 0, 0, 16, b'a', b'b', b'c', b'd']);
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+        buf.get_mut().extend_from_slice(&[b'e']);
+        assert!(codec.decode(&mut buf).is_err());
+    }
+
+    #[test]
+    fn buffered_corrupt_stream_is_error() {
+        let mut codec = ThriftCodec::<String, String>::with_mode(TransportMode::Buffered);
+        let mut buf = EasyBuf::new();
+        buf.get_mut().extend_from_slice(&[0x80, 0, 0, 0]);
+        assert!(codec.decode(&mut buf).is_err());
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Msg(i32);
+
+    impl Sequenced for Msg {
+        fn seq(&self) -> i32 {
+            self.0
+        }
+
+        fn set_seq(&mut self, seq: i32) {
+            self.0 = seq;
+        }
+    }
+
+    // seqid 0 marks oneway calls here
+    impl Oneway for Msg {
+        fn is_oneway(&self) -> bool {
+            self.0 == 0
+        }
+    }
+
+    impl OnewayReply for Msg {
+        fn oneway_reply(seq: i32) -> Self {
+            Msg(seq)
+        }
+    }
+
+    impl Serialize for Msg {
+        fn serialize<S>(&self, s: &mut S) -> Result<(), Error>
+            where S: Serializer + ThriftSerializer
+        {
+            self.0.serialize(s)
+        }
+    }
+
+    impl Deserialize for Msg {
+        fn deserialize<D>(de: &mut D) -> Result<Self, Error>
+            where D: Deserializer + ThriftDeserializer
+        {
+            Ok(Msg(try!(de.deserialize_i32())))
+        }
+    }
+
+    fn frame(codec: &mut ThriftCodec<Msg, Msg>, msg: Msg) -> EasyBuf {
+        let mut bytes = Vec::new();
+        codec.encode(msg, &mut bytes).unwrap();
+        EasyBuf::from(bytes)
+    }
+
+    #[test]
+    fn multiplex_client_maps_seqids() {
+        let mut plain = ThriftCodec::<Msg, Msg>::new();
+        let mut codec = ThriftMultiplexCodec::<Msg, Msg>::client(TransportMode::Framed, Limits::default());
+        let mut buf = Vec::new();
+        codec.encode((7, Msg(-1)), &mut buf).unwrap();
+        codec.encode((8, Msg(2)), &mut buf).unwrap();
+        // another caller reusing seqid 2 on the same connection
+        codec.encode((9, Msg(2)), &mut buf).unwrap();
+
+        // the request ids go on the wire
+        let mut sent = EasyBuf::from(buf);
+        for seq in 7..10 {
+            assert_eq!(plain.decode(&mut sent).unwrap(), Some(Msg(seq)));
+        }
+
+        assert_eq!(codec.decode(&mut frame(&mut plain, Msg(8))).unwrap(), Some((8, Msg(2))));
+        assert_eq!(codec.decode(&mut frame(&mut plain, Msg(9))).unwrap(), Some((9, Msg(2))));
+        assert_eq!(codec.decode(&mut frame(&mut plain, Msg(7))).unwrap(), Some((7, Msg(-1))));
+        assert!(codec.decode(&mut frame(&mut plain, Msg(8))).is_err());
+    }
+
+    #[test]
+    fn multiplex_server_accepts_duplicate_seqid() {
+        let mut plain = ThriftCodec::<Msg, Msg>::new();
+        let mut codec = ThriftMultiplexCodec::<Msg, Msg>::server(TransportMode::Framed, Limits::default());
+        assert_eq!(codec.decode(&mut frame(&mut plain, Msg(5))).unwrap(), Some((0, Msg(5))));
+        assert_eq!(codec.decode(&mut frame(&mut plain, Msg(5))).unwrap(), Some((1, Msg(5))));
+
+        let mut buf = Vec::new();
+        codec.encode((1, Msg(5)), &mut buf).unwrap();
+        codec.encode((0, Msg(5)), &mut buf).unwrap();
+        let mut sent = EasyBuf::from(buf);
+        assert_eq!(plain.decode(&mut sent).unwrap(), Some(Msg(5)));
+        assert_eq!(plain.decode(&mut sent).unwrap(), Some(Msg(5)));
+    }
+
+    #[test]
+    fn multiplex_client_forgets_oneway_calls() {
+        let mut codec = ThriftMultiplexCodec::<Msg, Msg>::client(TransportMode::Framed, Limits::default());
+        let mut buf = Vec::new();
+        codec.encode((1, Msg(0)), &mut buf).unwrap();
+        codec.encode((2, Msg(0)), &mut buf).unwrap();
+        assert!(!buf.is_empty());
+        assert!(codec.in_flight.is_empty());
+    }
+
+    // a connection on which replies arrive only once `answered` calls were sent
+    struct Peer {
+        sent: Vec<Msg>,
+        answered: usize,
+    }
+
+    impl Stream for Peer {
+        type Item = Msg;
+        type Error = io::Error;
+
+        fn poll(&mut self) -> Poll<Option<Msg>, io::Error> {
+            match self.sent.iter().position(|msg| msg.0 != 0) {
+                Some(i) if self.answered > 0 => {
+                    self.answered -= 1;
+                    Ok(Async::Ready(Some(self.sent.remove(i))))
+                }
+                _ => Ok(Async::NotReady),
+            }
+        }
+    }
+
+    impl Sink for Peer {
+        type SinkItem = Msg;
+        type SinkError = io::Error;
+
+        fn start_send(&mut self, msg: Msg) -> StartSend<Msg, io::Error> {
+            self.sent.push(msg);
+            Ok(AsyncSink::Ready)
+        }
+
+        fn poll_complete(&mut self) -> Poll<(), io::Error> {
+            Ok(Async::Ready(()))
+        }
+    }
+
+    #[test]
+    fn oneway_reply_keeps_pipeline_order() {
+        future::lazy(|| {
+            let peer = Peer { sent: Vec::new(), answered: 0 };
+            let mut transport = OnewayTransport::<_, Pipeline>::new(peer);
+            transport.start_send(Msg(1)).unwrap();
+            transport.start_send(Msg(0)).unwrap();
+            assert_eq!(transport.poll().unwrap(), Async::NotReady);
+
+            transport.inner.answered = 1;
+            assert_eq!(transport.poll().unwrap(), Async::Ready(Some(Msg(1))));
+            assert_eq!(transport.poll().unwrap(), Async::Ready(Some(Msg(0))));
+            assert_eq!(transport.replies, VecDeque::new());
+            future::ok::<(), ()>(())
+        }).wait().unwrap();
+    }
+}
@@ -15,9 +15,9 @@ use syntax::parse::{self, token, new_parser_from_source_str};
 use syntax::util::small_vector::SmallVector;
 use rustc_plugin::Registry;
 use tokio_thrift_codegen::parser::Document;
-use tokio_thrift_codegen::{compile, find_rust_namespace};
-use std::io::{Write, Read};
-use std::fs::File;
+use tokio_thrift_codegen::{compile_in, find_rust_namespace, load};
+use std::io::Write;
+use std::path::Path;
 
 macro_rules! panictry {
     ($e: expr) => {
@@ -30,18 +30,22 @@ macro_rules! panictry {
 }
 
 
-fn codegen<'cx>(cx: &'cx mut ExtCtxt, text: String, file: String) -> Box<MacResult + 'cx> {
+fn codegen<'cx>(cx: &'cx mut ExtCtxt, sp: Span, docs: Vec<Document>, file: String) -> Box<MacResult + 'cx> {
     let mut output = Vec::new();
-    let doc = Document::parse(&text)
-        .expect("failed to parse thrift file")
-        .expect("EOF while parsing thrift file");
-    {
-        let ns = find_rust_namespace(&doc).expect("cannot find namespace");
-        output.write_all(format!("mod {} {{", ns.module).as_ref())
-            .expect("internal error failed to write the vec");
+    // every file, included ones too, gets its own module
+    for doc in docs.iter() {
+        {
+            let ns = find_rust_namespace(doc).expect("cannot find namespace");
+            output.write_all(format!("mod {} {{", ns.module).as_ref())
+                .expect("internal error failed to write the vec");
+        }
+        // undefined names, bad field ids and the like
+        if let Err(e) = compile_in(doc.clone(), &docs, &mut output) {
+            cx.span_err(sp, &format!("{}", e));
+            return DummyResult::any(sp);
+        }
+        output.write_all(format!("}}").as_ref()).expect("internal error failed to write the vec");
     }
-    compile(doc, &mut output).expect("failed to generate code");
-    output.write_all(format!("}}").as_ref()).expect("internal error failed to write the vec");
     let output = match std::str::from_utf8(&output) {
         Ok(s) => s,
         Err(_) => "",
@@ -89,13 +93,15 @@ fn macro_thrift_file<'cx>(cx: &'cx mut ExtCtxt,
     };
 
 
-    let mut text = String::new();
-    File::open(&file)
-        .expect(&format!("thrift file not found: {}", &file))
-        .read_to_string(&mut text)
-        .expect(&format!("failed to read file: {}", &file));
+    let docs = match load(Path::new(&file), &[]) {
+        Ok(docs) => docs,
+        Err(e) => {
+            cx.span_err(sp, &format!("{}", e));
+            return DummyResult::any(sp);
+        }
+    };
 
-    codegen(cx, text, file)
+    codegen(cx, sp, docs, file)
 
 }
 
@@ -106,7 +112,15 @@ fn macro_thrift<'cx>(cx: &'cx mut ExtCtxt, sp: Span, tts: &[TokenTree]) -> Box<M
         None => return DummyResult::expr(sp),
     };
 
-    codegen(cx, text, "trift!".to_string())
+    let doc = match Document::parse(&text) {
+        Ok(doc) => doc,
+        Err(e) => {
+            cx.span_err(sp, &format!("thrift!:{}", e));
+            return DummyResult::any(sp);
+        }
+    };
+
+    codegen(cx, sp, vec![doc], "trift!".to_string())
 }
 
 #[plugin_registrar]
@@ -0,0 +1,62 @@
+#![feature(plugin)]
+#![plugin(tokio_thrift_macros)]
+
+extern crate futures;
+extern crate tokio_core;
+extern crate tokio_proto;
+extern crate tokio_service;
+extern crate tokio_thrift;
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io::Cursor;
+use futures::{Future, BoxFuture};
+use tokio_thrift::protocol::{BinaryProtocol, Serialize, Deserialize, ThriftDeserializer};
+
+thrift_file!("tests/annotation.thrift");
+
+use thrift_annotation::{Inventory, ShopService, ShopfindError};
+
+#[derive(Clone)]
+struct Handler;
+
+impl ShopService for Handler {
+    fn find(&self, kind: String) -> BoxFuture<Inventory, ShopfindError> {
+        futures::finished(Inventory { kind: kind, ..Default::default() }).boxed()
+    }
+}
+
+#[test]
+fn renamed_and_custom_typed_fields() {
+    let mut inventory = Inventory::default();
+    assert_eq!(inventory.queue, vec![1, 2].into_iter().collect::<VecDeque<_>>());
+
+    inventory.kind = "fruit".to_string();
+    inventory.counts = vec![("apple".to_string(), 3)].into_iter().collect::<BTreeMap<_, _>>();
+    inventory.queue.push_front(0);
+    inventory.tags = vec!["red".to_string()].into_iter().collect::<BTreeSet<_>>();
+
+    let mut buf = Vec::new();
+    inventory.serialize(&mut BinaryProtocol::new(&mut buf)).unwrap();
+    let de = Inventory::deserialize(&mut BinaryProtocol::new(Cursor::new(buf))).unwrap();
+    assert_eq!(de, inventory);
+}
+
+#[test]
+fn renamed_method_keeps_its_wire_name() {
+    use thrift_annotation::{ShopServiceMethodArgs, ShopfindArgs};
+
+    let mut buf = Vec::new();
+    ShopServiceMethodArgs::Afind(1, ShopfindArgs { kind: "fruit".to_string() })
+        .serialize(&mut BinaryProtocol::new(&mut buf))
+        .unwrap();
+    let msg = BinaryProtocol::new(Cursor::new(buf)).read_message_begin().unwrap();
+    assert_eq!(msg.name, "match");
+}
+
+#[test]
+fn renamed_method_round_trip() {
+    use thrift_annotation::{ShopClient, ShopServer};
+
+    let client = ShopClient::new(ShopServer::new(Handler));
+    assert_eq!(client.find("fruit".to_string()).wait().unwrap().kind, "fruit");
+}
@@ -0,0 +1,17 @@
+namespace rust thrift_annotation
+
+struct Inventory {
+  1: string type (rust.name = "kind");
+  2: map<string, i32> (rust.type = "::std::collections::BTreeMap") counts;
+  3: list<i32> queue = [1, 2] (rust.type = "::std::collections::VecDeque");
+  4: set<string> tags (rust.type = "::std::collections::BTreeSet", doc = "kept sorted");
+} (final = "true")
+
+enum Level {
+  LOW (label = "low"),
+  HIGH
+} (deprecated)
+
+service Shop {
+  Inventory match(1: string type (rust.name = "kind")) (rust.name = "find");
+} (version = "1")
@@ -9,7 +9,37 @@ extern crate tokio_thrift;
 
 thrift_file!("tests/const.thrift");
 
+use thrift_const::*;
+use tokio_thrift::protocol::Binary;
+
 #[test]
 fn const_available() {
     
 }
+
+#[test]
+fn base_constants() {
+    assert_eq!((A, B, C, D, E, F), (false, true, 2, -3, 4, 5));
+    assert_eq!((G, H), (0.6, -0.7));
+    assert_eq!(I(), "ok");
+    assert_eq!(S, 0xff);
+    assert_eq!(T(), Binary("café".as_bytes().to_vec()));
+}
+
+#[test]
+fn container_constants() {
+    assert_eq!(J(), vec!["a", "b", "c"]);
+    assert_eq!(K().get(&2).map(|s| &s[..]), Some("b"));
+    assert_eq!(K().len(), 2);
+    assert_eq!(L(), vec![-3, 1].into_iter().collect());
+    assert_eq!(R().get(&Color::RED), Some(&J()));
+}
+
+#[test]
+fn enum_and_struct_constants() {
+    assert_eq!(M, Color::GREEN);
+    assert_eq!(N, Color::RED);
+    assert_eq!(O(), Point { x: 1, y: -3, label: Some("ok".to_string()) });
+    assert_eq!(P(), vec![O(), Point { x: 2, y: 0, label: None }]);
+    assert_eq!(Q().value, "q");
+}
@@ -1,7 +1,9 @@
+include "included.thrift"
+
 namespace rust thrift_const
-/// thrift don't supports boolean constants?
-// const bool   A = 0;
-// const bool   B = 1;
+
+const bool   A = false;
+const bool   B = 1;
 const i16    C = 2;
 const i32    D = -3;
 const i64    E = 4;
@@ -9,5 +11,25 @@ const byte   F = 5;
 const double G = 0.6
 const double H = -0.7
 const string I = "ok";
-const list<string J = ["a", "b", "c"];
-const map <i32, string> = {1: "a", 2: "b"};
\ No newline at end of file
+const list<string> J = ["a", "b", "c"];
+const map<i32, string> K = {1: "a", 2: "b"};
+const set<i32> L = [D, 1];
+const Color M = Color.GREEN;
+const Color N = 0;
+const Point O = {"x": 1, "y": D, "label": I};
+const list<Point> P = [O, {"x": 2}];
+const included.Shared Q = {"key": 3, "value": "q"};
+const map<Color, list<string>> R = {Color.RED: J};
+const byte   S = -1;
+const binary T = "café";
+
+enum Color {
+  RED;
+  GREEN;
+}
+
+struct Point {
+  1: i32 x;
+  2: i32 y;
+  3: optional string label;
+}
@@ -0,0 +1,45 @@
+#![feature(plugin)]
+#![plugin(tokio_thrift_macros)]
+
+extern crate futures;
+extern crate tokio_core;
+extern crate tokio_proto;
+extern crate tokio_service;
+extern crate tokio_thrift;
+
+use std::io::Cursor;
+use std::collections::{HashMap, HashSet};
+use tokio_thrift::protocol::{BinaryProtocol, JsonProtocol, Binary, Serialize, Deserialize};
+
+thrift_file!("tests/container.thrift");
+
+#[test]
+fn container_round_trip() {
+    let mut tags = HashSet::new();
+    tags.insert("a".to_string());
+    let mut items = HashMap::new();
+    items.insert("x".to_string(), vec![thrift_container::Item { name: "y".to_string() }]);
+    let bag = thrift_container::Bag {
+        counts: vec![1, 2, 3],
+        tags: tags,
+        items: items,
+        extra: None,
+        data: Binary(vec![0, 0xff]),
+    };
+
+    let mut buf = Vec::new();
+    bag.serialize(&mut BinaryProtocol::new(&mut buf)).unwrap();
+    let mut de = BinaryProtocol::new(Cursor::new(buf));
+    assert_eq!(thrift_container::Bag::deserialize(&mut de).unwrap(), bag);
+}
+
+#[test]
+fn binary_is_a_string() {
+    let bag = thrift_container::Bag { data: Binary(b"thrift".to_vec()), ..Default::default() };
+    let mut buf = Vec::new();
+    bag.serialize(&mut JsonProtocol::new(&mut buf)).unwrap();
+    let json = String::from_utf8(buf.clone()).unwrap();
+    assert!(json.contains(r#""5":{"str":"dGhyaWZ0"}"#), "{}", json);
+    let mut de = JsonProtocol::new(Cursor::new(buf));
+    assert_eq!(thrift_container::Bag::deserialize(&mut de).unwrap(), bag);
+}
@@ -0,0 +1,13 @@
+namespace rust thrift_container
+
+struct Item {
+  1: required string name;
+}
+
+struct Bag {
+  1: required list<i32> counts;
+  2: required set<string> tags;
+  3: required map<string, list<Item>> items;
+  4: optional list<Item> extra;
+  5: required binary data;
+}
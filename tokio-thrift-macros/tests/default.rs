@@ -0,0 +1,44 @@
+#![feature(plugin)]
+#![plugin(tokio_thrift_macros)]
+
+extern crate futures;
+extern crate tokio_core;
+extern crate tokio_proto;
+extern crate tokio_service;
+extern crate tokio_thrift;
+
+use std::io::Cursor;
+use tokio_thrift::protocol::{BinaryProtocol, Deserialize, Serializer, ThriftSerializer, ThriftType};
+
+thrift_file!("tests/default.thrift");
+
+use thrift_default::Settings;
+
+#[test]
+fn idl_defaults() {
+    let settings = Settings::new("localhost".to_string());
+    assert_eq!(settings.retries, 3);
+    assert_eq!(settings.name, Some("main".to_string()));
+    assert_eq!(settings.ports, vec![80, 443]);
+    assert_eq!(settings.ids, vec![1, 2].into_iter().collect());
+    assert_eq!(settings.verbose, true);
+    assert_eq!(settings.host, "localhost");
+    assert_eq!(settings.limit, None);
+    assert_eq!(Settings { host: "localhost".to_string(), ..Settings::default() }, settings);
+}
+
+#[test]
+fn absent_fields_get_their_default() {
+    let mut buf = Vec::new();
+    {
+        let mut se = BinaryProtocol::new(&mut buf);
+        se.write_struct_begin("Settings").unwrap();
+        se.write_field_begin("host", ThriftType::String, 6).unwrap();
+        se.serialize_str("localhost").unwrap();
+        se.write_field_end().unwrap();
+        se.write_field_stop().unwrap();
+        se.write_struct_end().unwrap();
+    }
+    let de = Settings::deserialize(&mut BinaryProtocol::new(Cursor::new(buf))).unwrap();
+    assert_eq!(de, Settings::new("localhost".to_string()));
+}
@@ -0,0 +1,12 @@
+namespace rust thrift_default
+
+
+struct Settings {
+  1: i32 retries = 3;
+  2: optional string name = "main";
+  3: list<i16> ports = [80, 443];
+  4: set<i32> ids = [1, 2];
+  5: bool verbose = 1;
+  6: required string host;
+  7: optional i64 limit;
+}
@@ -0,0 +1,21 @@
+#![feature(plugin)]
+#![plugin(tokio_thrift_macros)]
+#![deny(unused_doc_comments)]
+
+extern crate futures;
+extern crate tokio_core;
+extern crate tokio_proto;
+extern crate tokio_service;
+extern crate tokio_thrift;
+
+thrift_file!("tests/doc.thrift");
+
+#[test]
+fn documented_items_available() {
+    use thrift_doc::{Color, Spot, Either, Oops};
+
+    let spot = Spot::new(1, 2);
+    assert_eq!(spot.color, Color::RED);
+    assert_eq!(Either::default(), Either::left(0));
+    assert_eq!(Oops::default().message, "");
+}
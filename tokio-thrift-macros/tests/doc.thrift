@@ -0,0 +1,41 @@
+namespace rust thrift_doc
+
+/**
+ * A shade of the rainbow.
+ */
+enum Color {
+  /** The first one. */
+  RED,
+  GREEN
+}
+
+/** Where something is, and what it looks like. */
+struct Spot {
+  /** Abscissa */
+  1: i32 x;
+  // not a doc comment
+  2: i32 y;
+  /**
+   * Defaults to red.
+   */
+  3: Color color = Color.RED
+}
+
+/** One or the other. */
+union Either {
+  /** The left one. */
+  1: i32 left;
+  2: string right
+}
+
+/** Bad things. */
+exception Oops {
+  /** What happened. */
+  1: string message
+}
+
+/** Spots things. */
+service Spotter {
+  /** Finds the spot of `color`. */
+  Spot find(/** The color to look for. */ 1: Color color) throws (1: Oops oops)
+}
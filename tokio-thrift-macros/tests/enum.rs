@@ -13,3 +13,17 @@ thrift_file!("tests/enum.thrift");
 fn enum_available() {
     
 }
+
+#[test]
+fn enum_round_trip() {
+    use std::io::Cursor;
+    use tokio_thrift::protocol::{BinaryProtocol, Serialize, Deserialize};
+    use thrift_enum::Foo;
+
+    assert_eq!(Foo::BAR as i32, 1);
+    let mut buf = Vec::new();
+    Foo::BAR.serialize(&mut BinaryProtocol::new(&mut buf)).unwrap();
+    assert_eq!(buf, [0, 0, 0, 1]);
+    assert_eq!(Foo::deserialize(&mut BinaryProtocol::new(Cursor::new(buf))).unwrap(), Foo::BAR);
+    assert!(Foo::deserialize(&mut BinaryProtocol::new(Cursor::new(vec![0, 0, 0, 7]))).is_err());
+}
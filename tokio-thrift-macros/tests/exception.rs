@@ -0,0 +1,25 @@
+#![feature(plugin)]
+#![plugin(tokio_thrift_macros)]
+
+extern crate futures;
+extern crate tokio_core;
+extern crate tokio_proto;
+extern crate tokio_service;
+extern crate tokio_thrift;
+
+use std::io::Cursor;
+use tokio_thrift::protocol::{BinaryProtocol, Serialize, Deserialize};
+
+thrift_file!("tests/exception.thrift");
+
+#[test]
+fn exception_round_trip() {
+    use thrift_exception::Foo;
+
+    let exc = Foo { FOO: true, BAR: Some("bar".to_string()) };
+    let mut buf = Vec::new();
+    exc.serialize(&mut BinaryProtocol::new(&mut buf)).unwrap();
+    let de = Foo::deserialize(&mut BinaryProtocol::new(Cursor::new(buf))).unwrap();
+    assert_eq!(de, exc);
+    assert_eq!(::std::error::Error::description(&de), "Thrift defined exception");
+}
@@ -1,4 +1,4 @@
-//namespace rust thrift_thrift
+namespace rust thrift_exception
 
 
 exception Foo {
@@ -0,0 +1,68 @@
+#![feature(plugin)]
+#![plugin(tokio_thrift_macros)]
+
+extern crate futures;
+extern crate tokio_core;
+extern crate tokio_proto;
+extern crate tokio_service;
+extern crate tokio_thrift;
+
+use std::io::Cursor;
+use futures::{Future, BoxFuture};
+use tokio_thrift::protocol::{BinaryProtocol, Serialize, Deserialize};
+
+thrift_file!("tests/extends.thrift");
+
+use thrift_included::{Shared, RegistryService, RegistrysizeError};
+use thrift_extends::{StoreService, StoregetError, CacheService, CacheevictError};
+
+#[derive(Clone)]
+struct Handler;
+
+impl RegistryService for Handler {
+    fn size(&self) -> BoxFuture<i32, RegistrysizeError> {
+        futures::finished(3).boxed()
+    }
+}
+
+impl StoreService for Handler {
+    fn get(&self, key: i32) -> BoxFuture<Shared, StoregetError> {
+        futures::finished(Shared { key: key, value: key.to_string() }).boxed()
+    }
+}
+
+impl CacheService for Handler {
+    fn evict(&self, _key: i32) -> BoxFuture<(), CacheevictError> {
+        futures::finished(()).boxed()
+    }
+}
+
+#[test]
+fn inherited_call_round_trip() {
+    use thrift_extends::{CacheServiceMethodArgs, CacheServiceMethodReturn};
+    use thrift_included::RegistrysizeArgs;
+
+    let mut buf = Vec::new();
+    CacheServiceMethodArgs::Asize(1, RegistrysizeArgs {}).serialize(&mut BinaryProtocol::new(&mut buf)).unwrap();
+    match CacheServiceMethodArgs::deserialize(&mut BinaryProtocol::new(Cursor::new(buf))).unwrap() {
+        CacheServiceMethodArgs::Asize(1, _) => (),
+        args => panic!("unexpected call {:?}", args),
+    }
+
+    let mut buf = Vec::new();
+    CacheServiceMethodReturn::Rsize(2, Ok(3)).serialize(&mut BinaryProtocol::new(&mut buf)).unwrap();
+    match CacheServiceMethodReturn::deserialize(&mut BinaryProtocol::new(Cursor::new(buf))).unwrap() {
+        CacheServiceMethodReturn::Rsize(2, Ok(3)) => (),
+        ret => panic!("unexpected reply {:?}", ret),
+    }
+}
+
+#[test]
+fn client_reaches_every_ancestor() {
+    use thrift_extends::{CacheClient, CacheServer};
+
+    let client = CacheClient::new(CacheServer::new(Handler));
+    assert_eq!(client.size().wait().unwrap(), 3);
+    assert_eq!(client.get(4).wait().unwrap(), Shared { key: 4, value: "4".to_string() });
+    client.evict(4).wait().unwrap();
+}
@@ -0,0 +1,12 @@
+include "included.thrift"
+
+namespace rust thrift_extends
+
+
+service Store extends included.Registry {
+  included.Shared get(1: i32 key);
+}
+
+service Cache extends Store {
+  void evict(1: i32 key);
+}
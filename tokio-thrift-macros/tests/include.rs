@@ -0,0 +1,42 @@
+#![feature(plugin)]
+#![plugin(tokio_thrift_macros)]
+
+extern crate futures;
+extern crate tokio_core;
+extern crate tokio_proto;
+extern crate tokio_service;
+extern crate tokio_thrift;
+
+use std::io::Cursor;
+use tokio_thrift::protocol::{BinaryProtocol, Serialize, Deserialize};
+
+thrift_file!("tests/include.thrift");
+
+use thrift_included::Shared;
+
+fn shared(key: i32) -> Shared {
+    Shared { key: key, value: key.to_string() }
+}
+
+#[test]
+fn included_struct_field() {
+    use thrift_include::Wrapper;
+
+    let wrapper = Wrapper { inner: shared(1), all: vec![shared(2), shared(3)] };
+    let mut buf = Vec::new();
+    wrapper.serialize(&mut BinaryProtocol::new(&mut buf)).unwrap();
+    let de = Wrapper::deserialize(&mut BinaryProtocol::new(Cursor::new(buf))).unwrap();
+    assert_eq!(de, wrapper);
+}
+
+#[test]
+fn included_struct_result() {
+    use thrift_include::FooServiceMethodReturn;
+
+    let mut buf = Vec::new();
+    FooServiceMethodReturn::Rget(1, Ok(shared(4))).serialize(&mut BinaryProtocol::new(&mut buf)).unwrap();
+    match FooServiceMethodReturn::deserialize(&mut BinaryProtocol::new(Cursor::new(buf))).unwrap() {
+        FooServiceMethodReturn::Rget(1, Ok(ref got)) => assert_eq!(got, &shared(4)),
+        ret => panic!("unexpected reply {:?}", ret),
+    }
+}
@@ -0,0 +1,13 @@
+include "included.thrift"
+
+namespace rust thrift_include
+
+
+struct Wrapper {
+  1: required included.Shared inner;
+  2: required list<included.Shared> all;
+}
+
+service Foo {
+  included.Shared get(1: i32 key);
+}
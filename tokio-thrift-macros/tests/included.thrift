@@ -0,0 +1,11 @@
+namespace rust thrift_included
+
+
+struct Shared {
+  1: required i32 key;
+  2: required string value;
+}
+
+service Registry {
+  i32 size();
+}
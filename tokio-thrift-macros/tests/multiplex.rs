@@ -0,0 +1,56 @@
+#![feature(plugin)]
+#![plugin(tokio_thrift_macros)]
+
+extern crate futures;
+extern crate tokio_core;
+extern crate tokio_proto;
+extern crate tokio_service;
+extern crate tokio_thrift;
+
+use futures::{Future, Stream, BoxFuture};
+use tokio_core::net::{TcpListener, TcpStream};
+use tokio_core::reactor::Core;
+use tokio_proto::{BindClient, BindServer};
+use tokio_thrift::tokio::ThriftMultiplexProto;
+
+thrift_file!("tests/multiplex.thrift");
+
+use thrift_multiplex::{EchoService, EchoServiceMethodArgs, EchoServiceMethodReturn, EchoClient, EchoServer,
+                       EchoechoError};
+
+#[derive(Clone)]
+struct Handler;
+
+impl EchoService for Handler {
+    fn echo(&self, s: String) -> BoxFuture<String, EchoechoError> {
+        futures::finished(s).boxed()
+    }
+}
+
+#[test]
+fn clients_sharing_a_connection() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let proto = ThriftMultiplexProto::<EchoServiceMethodArgs, EchoServiceMethodReturn>::new;
+
+    let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server_handle = handle.clone();
+    handle.spawn(listener.incoming()
+        .for_each(move |(socket, _)| {
+            proto().bind_server(&server_handle, socket, EchoServer::new(Handler));
+            Ok(())
+        })
+        .map_err(|_| ()));
+
+    let socket = core.run(TcpStream::connect(&addr, &handle)).unwrap();
+    let connection = proto().bind_client(&handle, socket);
+    // both clients start their seqids at 1
+    let a = EchoClient::new(connection.clone());
+    let b = EchoClient::new(connection);
+
+    let calls = a.echo("a".to_string()).join(b.echo("b".to_string()));
+    assert_eq!(core.run(calls).unwrap(), ("a".to_string(), "b".to_string()));
+    // the connection is still usable
+    assert_eq!(core.run(b.echo("c".to_string())).unwrap(), "c");
+}
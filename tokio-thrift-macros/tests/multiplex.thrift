@@ -0,0 +1,5 @@
+namespace rust thrift_multiplex
+
+service Echo {
+  string echo(1: string s);
+}
@@ -0,0 +1,67 @@
+#![feature(plugin)]
+#![plugin(tokio_thrift_macros)]
+
+extern crate futures;
+extern crate tokio_core;
+extern crate tokio_proto;
+extern crate tokio_service;
+extern crate tokio_thrift;
+
+use std::io::Cursor;
+use tokio_thrift::protocol::{BinaryProtocol, Serialize, Deserialize, Serializer, ThriftSerializer, ThriftType, Error};
+
+thrift_file!("tests/required.thrift");
+
+use thrift_required::Entry;
+
+// writes a struct holding the single field `1: string`, or no field at all
+fn struct_bytes(key: Option<&str>) -> Vec<u8> {
+    let mut buf = Vec::new();
+    {
+        let mut se = BinaryProtocol::new(&mut buf);
+        se.write_struct_begin("Entry").unwrap();
+        if let Some(key) = key {
+            se.write_field_begin("key", ThriftType::String, 1).unwrap();
+            se.serialize_str(key).unwrap();
+            se.write_field_end().unwrap();
+        }
+        se.write_field_stop().unwrap();
+        se.write_struct_end().unwrap();
+    }
+    buf
+}
+
+#[test]
+fn default_requiredness_falls_back() {
+    let de = Entry::deserialize(&mut BinaryProtocol::new(Cursor::new(struct_bytes(Some("k"))))).unwrap();
+    assert_eq!(de, Entry { key: "k".to_string(), hits: 0, note: None });
+
+    // fields of default requiredness are always written
+    let mut buf = Vec::new();
+    de.serialize(&mut BinaryProtocol::new(&mut buf)).unwrap();
+    assert!(buf.len() > struct_bytes(Some("k")).len());
+}
+
+#[test]
+fn missing_required_field() {
+    match Entry::deserialize(&mut BinaryProtocol::new(Cursor::new(struct_bytes(None)))) {
+        Err(Error::MissingRequiredField { ref struct_, ref field }) => {
+            assert_eq!(struct_, "Entry");
+            assert_eq!(field, "key");
+        }
+        ret => panic!("unexpected result {:?}", ret),
+    }
+}
+
+#[test]
+fn missing_required_argument() {
+    use thrift_required::IndexputArgs;
+
+    match IndexputArgs::deserialize(&mut BinaryProtocol::new(Cursor::new(struct_bytes(None)))) {
+        Err(Error::MissingRequiredField { ref struct_, ref field }) => {
+            assert_eq!(struct_, "put_args");
+            assert_eq!(field, "entry");
+        }
+        ret => panic!("unexpected result {:?}", ret),
+    }
+}
@@ -0,0 +1,12 @@
+namespace rust thrift_required
+
+
+struct Entry {
+  1: required string key;
+  2: i32 hits;
+  3: optional string note;
+}
+
+service Index {
+  void put(1: required Entry entry, 2: optional i32 ttl);
+}
@@ -7,9 +7,101 @@ extern crate tokio_proto;
 extern crate tokio_service;
 extern crate tokio_thrift;
 
+use std::io::Cursor;
+use tokio_thrift::protocol::{BinaryProtocol, Serialize, Deserialize, Sequenced};
+
 thrift_file!("tests/service.thrift");
 
 #[test]
 fn service_available() {
     
 }
+
+#[test]
+fn seq_round_trip() {
+    use thrift_service::{FooServiceMethodArgs, FooServiceMethodReturn, FoobazArgs};
+
+    let mut buf = Vec::new();
+    let args = FooServiceMethodArgs::Abaz(-42, FoobazArgs { token: "t".to_string(), id: 3 });
+    args.serialize(&mut BinaryProtocol::new(&mut buf)).unwrap();
+    let args = FooServiceMethodArgs::deserialize(&mut BinaryProtocol::new(Cursor::new(buf))).unwrap();
+    assert_eq!(args.seq(), -42);
+
+    let mut buf = Vec::new();
+    let ret = FooServiceMethodReturn::Rbar(1 << 20, Ok(true));
+    ret.serialize(&mut BinaryProtocol::new(&mut buf)).unwrap();
+    let ret = FooServiceMethodReturn::deserialize(&mut BinaryProtocol::new(Cursor::new(buf))).unwrap();
+    assert_eq!(ret.seq(), 1 << 20);
+}
+
+#[test]
+fn void_reply_round_trip() {
+    use thrift_service::FooServiceMethodReturn;
+
+    let mut buf = Vec::new();
+    FooServiceMethodReturn::Rbaz(3, Ok(())).serialize(&mut BinaryProtocol::new(&mut buf)).unwrap();
+    match FooServiceMethodReturn::deserialize(&mut BinaryProtocol::new(Cursor::new(buf))).unwrap() {
+        FooServiceMethodReturn::Rbaz(3, Ok(())) => (),
+        ret => panic!("unexpected reply {:?}", ret),
+    }
+}
+
+#[test]
+fn exception_round_trip() {
+    use thrift_service::{FooServiceMethodReturn, FoobarError};
+    use tokio_thrift::{ApplicationException, ApplicationExceptionKind};
+
+    let e = ApplicationException::new(ApplicationExceptionKind::InternalError, "boom");
+    let mut buf = Vec::new();
+    FooServiceMethodReturn::Rbar(4, Err(e.clone().into())).serialize(&mut BinaryProtocol::new(&mut buf)).unwrap();
+    match FooServiceMethodReturn::deserialize(&mut BinaryProtocol::new(Cursor::new(buf))).unwrap() {
+        FooServiceMethodReturn::Rbar(4, Err(FoobarError::Application(ref got))) if *got == e => (),
+        ret => panic!("unexpected reply {:?}", ret),
+    }
+}
+
+#[test]
+fn unknown_method_is_invalid() {
+    use thrift_service::FooServiceMethodArgs;
+    use tokio_thrift::ApplicationExceptionKind;
+    use tokio_thrift::protocol::{ThriftSerializer, ThriftMessageType};
+
+    let mut buf = Vec::new();
+    {
+        let mut se = BinaryProtocol::new(&mut buf);
+        se.write_message_begin("qux", ThriftMessageType::Call, 6).unwrap();
+        se.write_struct_begin("qux_args").unwrap();
+        se.write_field_stop().unwrap();
+        se.write_struct_end().unwrap();
+        se.write_message_end().unwrap();
+    }
+    match FooServiceMethodArgs::deserialize(&mut BinaryProtocol::new(Cursor::new(buf))).unwrap() {
+        FooServiceMethodArgs::Invalid(6, ref name, ref e) if name == "qux" =>
+            assert_eq!(e.kind, ApplicationExceptionKind::UnknownMethod),
+        args => panic!("unexpected call {:?}", args),
+    }
+}
+
+#[test]
+fn oneway_call() {
+    use thrift_service::{FooServiceMethodArgs, FooServiceMethodReturn, FoopingArgs};
+    use tokio_thrift::protocol::{ThriftDeserializer, ThriftMessageType, Oneway, OnewayReply};
+
+    let args = FooServiceMethodArgs::Aping(3, FoopingArgs { n: 1 });
+    assert!(args.is_oneway());
+    let mut buf = Vec::new();
+    args.serialize(&mut BinaryProtocol::new(&mut buf)).unwrap();
+    let msg = BinaryProtocol::new(Cursor::new(&buf[..])).read_message_begin().unwrap();
+    assert_eq!(msg.ty, ThriftMessageType::Oneway);
+    match FooServiceMethodArgs::deserialize(&mut BinaryProtocol::new(Cursor::new(buf))).unwrap() {
+        FooServiceMethodArgs::Aping(3, FoopingArgs { n: 1 }) => (),
+        args => panic!("unexpected call {:?}", args),
+    }
+
+    // servers never answer a oneway call
+    let ret = FooServiceMethodReturn::oneway_reply(3);
+    assert!(ret.is_oneway());
+    let mut buf = Vec::new();
+    ret.serialize(&mut BinaryProtocol::new(&mut buf)).unwrap();
+    assert!(buf.is_empty());
+}
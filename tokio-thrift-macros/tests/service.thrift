@@ -4,4 +4,5 @@ namespace rust thrift_service
 service Foo {
   bool bar(1: string token);
   void baz(1: string token, 2: i32 id);
+  oneway void ping(1: i32 n);
 }
\ No newline at end of file
@@ -0,0 +1,36 @@
+#![feature(plugin)]
+#![plugin(tokio_thrift_macros)]
+
+extern crate futures;
+extern crate tokio_core;
+extern crate tokio_proto;
+extern crate tokio_service;
+extern crate tokio_thrift;
+
+use std::io::Cursor;
+use std::collections::HashMap;
+use tokio_thrift::protocol::{BinaryProtocol, Serialize, Deserialize};
+
+thrift_file!("tests/skip.thrift");
+
+#[test]
+fn skip_unknown_and_mistyped_fields() {
+    let mut extra = HashMap::new();
+    extra.insert(1, vec![2, 3]);
+    let new = thrift_skip::New {
+        id: 7,
+        count: Some("many".to_string()),
+        nested: thrift_skip::Nested { names: vec!["a".to_string()] },
+        extra: extra,
+    };
+
+    let mut buf = Vec::new();
+    new.serialize(&mut BinaryProtocol::new(&mut buf)).unwrap();
+    // a trailing value must still be readable after the skipped fields
+    12i32.serialize(&mut BinaryProtocol::new(&mut buf)).unwrap();
+
+    let mut de = BinaryProtocol::new(Cursor::new(buf));
+    let old = thrift_skip::Old::deserialize(&mut de).unwrap();
+    assert_eq!(old, thrift_skip::Old { id: 7, count: None });
+    assert_eq!(i32::deserialize(&mut de).unwrap(), 12);
+}
@@ -0,0 +1,17 @@
+namespace rust thrift_skip
+
+struct Old {
+  1: required i32 id;
+  2: optional i32 count;
+}
+
+struct Nested {
+  1: required list<string> names;
+}
+
+struct New {
+  1: required i32 id;
+  2: optional string count;
+  3: required Nested nested;
+  4: required map<i32, list<i64>> extra;
+}
@@ -13,3 +13,18 @@ thrift_file!("tests/struct.thrift");
 fn struct_available() {
     
 }
+
+#[test]
+fn implicit_field_ids() {
+    use std::io::Cursor;
+    use tokio_thrift::protocol::{BinaryProtocol, Serialize, Deserialize};
+    use thrift_struct::Bare;
+
+    let bare = Bare { name: "n".to_string(), count: 1 };
+    let mut buf = Vec::new();
+    bare.serialize(&mut BinaryProtocol::new(&mut buf)).unwrap();
+    // field headers carry the ids -1 and -2
+    assert_eq!(&buf[..3], &[11, 0xff, 0xff]);
+    assert_eq!(&buf[8..11], &[8, 0xff, 0xfe]);
+    assert_eq!(Bare::deserialize(&mut BinaryProtocol::new(Cursor::new(buf))).unwrap(), bare);
+}
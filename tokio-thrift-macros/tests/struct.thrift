@@ -4,4 +4,9 @@ namespace rust thrift_struct
 struct Foo {
   1: required bool FOO;
   2: optional string BAR;
-}
\ No newline at end of file
+}
+
+struct Bare {
+  string name;
+  i32 count;
+}
@@ -0,0 +1,38 @@
+#![feature(plugin)]
+#![plugin(tokio_thrift_macros)]
+
+extern crate futures;
+extern crate tokio_core;
+extern crate tokio_proto;
+extern crate tokio_service;
+extern crate tokio_thrift;
+
+use std::io::Cursor;
+use tokio_thrift::protocol::{BinaryProtocol, Serialize, Deserialize};
+
+thrift_file!("tests/throws.thrift");
+
+use thrift_throws::{Ex2, FooServiceMethodReturn, FoobarError};
+
+fn round_trip(ret: FooServiceMethodReturn) -> FooServiceMethodReturn {
+    let mut buf = Vec::new();
+    ret.serialize(&mut BinaryProtocol::new(&mut buf)).unwrap();
+    FooServiceMethodReturn::deserialize(&mut BinaryProtocol::new(Cursor::new(buf))).unwrap()
+}
+
+#[test]
+fn success_round_trip() {
+    match round_trip(FooServiceMethodReturn::Rbar(3, Ok(true))) {
+        FooServiceMethodReturn::Rbar(3, Ok(true)) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn declared_exception_round_trip() {
+    match round_trip(FooServiceMethodReturn::Rbar(4, Err(FoobarError::ex2(Ex2 {})))) {
+        FooServiceMethodReturn::Rbar(4, Err(FoobarError::ex2(Ex2 {}))) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
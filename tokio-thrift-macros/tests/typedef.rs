@@ -11,5 +11,10 @@ thrift_file!("tests/typedef.thrift");
 
 #[test]
 fn typedef_available() {
-    
+    use thrift_typedef::{Name, Team};
+
+    let lead: Name = "bob".to_string();
+    let team = Team { lead: lead, members: vec!["eve".to_string()] };
+    assert_eq!(Team::default().lead, "ann");
+    assert_eq!(team.members.len(), 1);
 }
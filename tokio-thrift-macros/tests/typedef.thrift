@@ -1,3 +1,9 @@
 namespace rust thrift_typedef
 
-typedef foo string
\ No newline at end of file
+typedef string Name
+typedef list<Name> Names
+
+struct Team {
+  1: Name lead = "ann";
+  2: Names members;
+}
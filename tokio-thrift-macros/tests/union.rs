@@ -0,0 +1,55 @@
+#![feature(plugin)]
+#![plugin(tokio_thrift_macros)]
+
+extern crate futures;
+extern crate tokio_core;
+extern crate tokio_proto;
+extern crate tokio_service;
+extern crate tokio_thrift;
+
+use std::io::Cursor;
+use tokio_thrift::protocol::{BinaryProtocol, Serialize, Deserialize, Error};
+use tokio_thrift::protocol::{ThriftSerializer, ThriftType};
+
+thrift_file!("tests/union.thrift");
+
+use thrift_union::Foo;
+
+#[test]
+fn union_round_trip() {
+    for foo in vec![Foo::FOO("foo".to_string()), Foo::BAR(42), Foo::BAZ(0.5)] {
+        let mut buf = Vec::new();
+        foo.serialize(&mut BinaryProtocol::new(&mut buf)).unwrap();
+        let de = Foo::deserialize(&mut BinaryProtocol::new(Cursor::new(buf))).unwrap();
+        assert_eq!(de, foo);
+    }
+}
+
+fn read_fields(fields: &[(i16, i32)]) -> Result<Foo, Error> {
+    let mut buf = Vec::new();
+    {
+        let mut s = BinaryProtocol::new(&mut buf);
+        s.write_struct_begin("Foo").unwrap();
+        for &(id, val) in fields {
+            s.write_field_begin("", ThriftType::I32, id).unwrap();
+            val.serialize(&mut s).unwrap();
+            s.write_field_end().unwrap();
+        }
+        s.write_field_stop().unwrap();
+        s.write_struct_end().unwrap();
+    }
+    Foo::deserialize(&mut BinaryProtocol::new(Cursor::new(buf)))
+}
+
+#[test]
+fn union_needs_exactly_one_field() {
+    assert_eq!(read_fields(&[(2, 7)]).unwrap(), Foo::BAR(7));
+    match read_fields(&[]) {
+        Err(Error::BadUnion(ref name, 0)) if name == "Foo" => (),
+        ret => panic!("unexpected {:?}", ret),
+    }
+    match read_fields(&[(2, 7), (2, 8)]) {
+        Err(Error::BadUnion(_, 2)) => (),
+        ret => panic!("unexpected {:?}", ret),
+    }
+}
@@ -1,6 +1,7 @@
-//namespace rust thrift_union
+namespace rust thrift_union
 
 union Foo {
   1: string FOO;
   2: i32 BAR;
+  3: double BAZ;
 }
\ No newline at end of file